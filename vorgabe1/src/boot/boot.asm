@@ -159,10 +159,19 @@ _init_longmode:
 	; Seitentabelle anlegen (Ohne geht es nicht)
 	call   _setup_paging
 
+	; Unterstuetzt die CPU das NX-Bit? (CPUID 0x80000001, EDX Bit 20)
+	mov    eax, 0x80000001
+	cpuid
+	mov    esi, edx
+
 	; Long-Mode (fürs erste noch im Compatibility-Mode) aktivieren
 	mov    ecx, 0x0C0000080 ; EFER (Extended Feature Enable Register) auswaehlen
 	rdmsr
 	or     eax, 1 << 8 ; LME (Long Mode Enable)
+	test   esi, 1 << 20
+	jz     _no_nx
+	or     eax, 1 << 11 ; NXE (No-Execute Enable), sonst ist Bit 63 in den Seitentabellen reserviert
+_no_nx:
 	wrmsr
 
 	; Paging aktivieren
@@ -101,6 +101,34 @@ pub struct MmapEntry {
     pub typ: u32,
 }
 
+// Eintrag in der Modul-Tabelle ('mods_addr'), 'mod_end' ist exklusiv
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct MultibootModule {
+    pub mod_start: u32,
+    pub mod_end: u32,
+    pub string: u32,
+    pub reserved: u32,
+}
+
+// Von GRUB geladenes Modul (z.B. ein User-Programm)
+pub struct BootModule {
+    pub region: PhysRegion,  // [mod_start, mod_end - 1]
+    pub name: &'static str,  // Kommandozeile des Moduls in 'grub.cfg'
+}
+
+impl BootModule {
+    // Inhalt des Moduls (Speicher ist identisch gemappt)
+    pub fn data(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self.region.start as *const u8,
+                (self.region.end + 1 - self.region.start) as usize,
+            )
+        }
+    }
+}
+
 #[derive(Debug)]
 #[repr(u32)]
 pub enum MmapType {
@@ -176,6 +204,33 @@ pub fn get_free_memory(
     // Und danach der temporäre Heap
     reserved.push(heap_region);
 
+    // Module und deren Namen duerfen nicht als Page-Frames vergeben werden,
+    // sie werden erst nach dem Einrichten der Threads geladen
+    for module in module_table(mb_info) {
+        let mod_start = module.mod_start as u64;
+        let mod_end = module.mod_end as u64;
+        if mod_end > mod_start {
+            reserved.push(PhysRegion {
+                start: mod_start & !0xFFF,
+                end: ((mod_end + 0xFFF) & !0xFFF) - 1,
+            });
+        }
+        if module.string != 0 {
+            let name = module_name(module);
+            reserved.push(PhysRegion {
+                start: module.string as u64,
+                end: module.string as u64 + name.len() as u64,
+            });
+        }
+    }
+    if flags & 0x8 != 0 && mb_info.mods_count != 0 {
+        let mods_addr = mb_info.mods_addr as u64;
+        reserved.push(PhysRegion {
+            start: mods_addr,
+            end: mods_addr + (mb_info.mods_count as usize * size_of::<MultibootModule>()) as u64 - 1,
+        });
+    }
+
     // 15 - 16 MB ignorieren wir (ISA hole)
     let region_isa = PhysRegion {
         start: 0xF0_0000,
@@ -268,6 +323,107 @@ fn check_for_overlapping(free: Vec<PhysRegion>, reserved_region: &PhysRegion) ->
     free_then
 }
 
+//
+// Kommandozeile des Kernels lesen. Der String liegt im Speicher des
+// Bootloaders und muss vor dem Einrichten der Page-Frames gelesen werden.
+//
+pub fn get_cmdline(mbi_ptr: u64) -> Option<&'static str> {
+    let mb_info = unsafe { MultibootInfo::read(mbi_ptr) };
+    let flags = mb_info.flags;
+
+    // Kommandozeile vorhanden?
+    if flags & 0x4 == 0 || mb_info.cmdline == 0 {
+        return None;
+    }
+
+    // Null-terminierten String lesen
+    unsafe {
+        let start = mb_info.cmdline as u64 as *const u8;
+        let mut len = 0;
+        while *start.add(len) != 0 {
+            len += 1;
+        }
+        core::str::from_utf8(core::slice::from_raw_parts(start, len)).ok()
+    }
+}
+
+//
+// Wert einer Option 'key=value' von der Kommandozeile lesen
+// (z.B. "sched=mlfq" in 'grub.cfg')
+//
+pub fn get_cmdline_arg(mbi_ptr: u64, key: &str) -> Option<&'static str> {
+    // Das erste Wort ist der Pfad des Kernels
+    get_cmdline(mbi_ptr)?
+        .split_whitespace()
+        .skip(1)
+        .find_map(|arg| arg.strip_prefix(key)?.strip_prefix('='))
+}
+
+//
+// Modul-Tabelle lesen. Leer, falls GRUB keine Module geladen hat.
+//
+fn module_table(mb_info: &MultibootInfo) -> &'static [MultibootModule] {
+    let flags = mb_info.flags;
+    if flags & 0x8 == 0 || mb_info.mods_count == 0 {
+        return &[];
+    }
+    unsafe {
+        core::slice::from_raw_parts(
+            mb_info.mods_addr as u64 as *const MultibootModule,
+            mb_info.mods_count as usize,
+        )
+    }
+}
+
+// Null-terminierten Namen eines Moduls lesen
+fn module_name(module: &MultibootModule) -> &'static str {
+    if module.string == 0 {
+        return "";
+    }
+    unsafe {
+        let start = module.string as u64 as *const u8;
+        let mut len = 0;
+        while *start.add(len) != 0 {
+            len += 1;
+        }
+        core::str::from_utf8(core::slice::from_raw_parts(start, len)).unwrap_or("")
+    }
+}
+
+//
+// Von GRUB geladene Module ermitteln ('module' in 'grub.cfg')
+// Die Speicherbereiche werden in 'get_free_memory' reserviert.
+//
+pub fn get_modules(mbi_ptr: u64) -> Vec<BootModule> {
+    let mb_info = unsafe { MultibootInfo::read(mbi_ptr) };
+
+    module_table(mb_info)
+        .iter()
+        .filter(|m| m.mod_end > m.mod_start)
+        .map(|m| BootModule {
+            region: PhysRegion {
+                start: m.mod_start as u64,
+                end: m.mod_end as u64 - 1,
+            },
+            name: module_name(m),
+        })
+        .collect()
+}
+
+//
+// Letzte belegte Adresse aller Module (ohne Allokation, der Heap
+// existiert noch nicht). GRUB legt die Module direkt hinter den Kernel.
+//
+pub fn get_modules_end(mbi_ptr: u64) -> Option<u64> {
+    let mb_info = unsafe { MultibootInfo::read(mbi_ptr) };
+
+    module_table(mb_info)
+        .iter()
+        .filter(|m| m.mod_end > m.mod_start)
+        .map(|m| m.mod_end as u64 - 1)
+        .max()
+}
+
 //
 // Debug-Funktion zur Ausgabe verschiedener Multiboot-Infos
 //
@@ -278,6 +434,24 @@ pub fn dump(mbi_ptr: u64) {
     kprintln!("Multiboot-Infos = {:x}", flags);
     kprintln!("   flags = {:x}", flags);
 
+    // Kommandozeile des Kernels
+    if let Some(cmdline) = get_cmdline(mbi_ptr) {
+        kprintln!("   cmdline = {}", cmdline);
+    }
+
+    // Geladene Module
+    for (i, module) in module_table(mb_info).iter().enumerate() {
+        let mod_start = module.mod_start;
+        let mod_end = module.mod_end;
+        kprintln!(
+            "   module {}: [0x{:x}, 0x{:x}) '{}'",
+            i,
+            mod_start,
+            mod_end,
+            module_name(module)
+        );
+    }
+
     // Allgemeine Speicherinfos
     if flags & 0x1 != 0 {
         let mem_lower = mb_info.mem_lower;
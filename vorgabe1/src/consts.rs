@@ -21,6 +21,12 @@ pub const PAGE_SIZE: usize = 0x1000;
 // 1 MB Heap füsr das Einrichten des Systems (siehe 'kmain')
 pub const KERNEL_HEAP_SIZE: usize = 0x10_0000;
 
+// Obergrenze fuer den Kernel-Heap inklusive aller Erweiterungen (siehe 'list.rs')
+pub const KERNEL_HEAP_MAX_SIZE: usize = 0x100_0000;
+
+// Ist der Kernel-Heap voll, waechst er um mindestens so viel
+pub const KERNEL_HEAP_GROW_SIZE: usize = 0x1_0000;
+
 // Kachelgroesse = 4 KB
 pub const PAGE_FRAME_SIZE: usize = 0x1000;
 
@@ -41,7 +47,27 @@ pub const KERNEL_VM_END: usize = KERNEL_VM_SIZE - 1;
 
 //
 // Konstanten fuer den virtuellen Adresseraum des User-Modes
-// Vorerst nur fuer den Stack des User-Mode-Threads, beginnt ab 64 TiB - 1.
+// Die User-Stacks wachsen von 64 TiB abwaerts.
 //
 pub const USER_STACK_VM_START:usize = 0x4000_0000_0000;
-pub const USER_STACK_VM_END: usize = USER_STACK_VM_START + STACK_SIZE - 1;
+
+// Groesse eines User-Stacks = 8 MiB, Page-Frames werden erst beim ersten
+// Zugriff alloziert (siehe 'page_fault')
+pub const USER_STACK_SIZE: usize = 0x80_0000;
+
+// Jeder Thread eines Prozesses bekommt einen eigenen User-Stack. Die Stacks
+// liegen unterhalb von USER_STACK_VM_START untereinander, jeweils mit einer
+// nicht gemappten Guard-Page darunter, damit ein Ueberlauf erkannt wird und
+// nicht den Nachbar-Stack trifft.
+pub const USER_STACK_STRIDE: usize = USER_STACK_SIZE + PAGE_SIZE;
+pub const MAX_USER_STACKS: usize = 64;
+
+// Unterstes Ende des Stack-Bereichs, Programm und Heap muessen darunter liegen
+pub const USER_STACKS_VM_BOTTOM: usize = USER_STACK_VM_START - MAX_USER_STACKS * USER_STACK_STRIDE;
+
+// Start des Heaps, falls kein Programm geladen wurde (sonst direkt hinter
+// dem Programm, siehe 'elf'). Der Heap waechst bis USER_MMAP_VM_START.
+pub const USER_HEAP_VM_START: usize = KERNEL_VM_SIZE;
+
+// Bereich, in dem 'mmap' Adressen waehlt (ohne MAP_FIXED), bis USER_STACKS_VM_BOTTOM
+pub const USER_MMAP_VM_START: usize = 0x2000_0000_0000; // 32 TiB
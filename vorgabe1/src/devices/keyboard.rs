@@ -18,16 +18,37 @@ use crate::kernel::cpu;
 use crate::kernel::interrupts::int_dispatcher;
 use crate::kernel::interrupts::isr;
 use crate::kernel::interrupts::pic;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::wait_queue::WaitQueue;
 
 // called from mylib/input.rs
 pub fn get_lastkey() -> u8 {
     LAST_KEY.swap(0, Ordering::SeqCst) as u8
 }
 
+// called from mylib/input.rs, blocks the calling thread until a key is pressed
+pub fn wait_for_key() -> u8 {
+    loop {
+        // Interrupts aus, damit der Tastendruck nicht zwischen Pruefen
+        // und Blockieren verloren geht
+        let irq = cpu::disable_int_nested();
+        let k = get_lastkey();
+        if k != 0 {
+            cpu::enable_int_nested(irq);
+            return k;
+        }
+        Scheduler::block(&KEY_WAITERS);
+        cpu::enable_int_nested(irq);
+    }
+}
+
 // accessed by ISR, storing last read ASCII code
 // and by get_lastkey, see above
 static LAST_KEY: AtomicU8 = AtomicU8::new(0);
 
+// threads blocked in 'wait_for_key', released by the ISR
+static KEY_WAITERS: WaitQueue = WaitQueue::new();
+
 // Global thread-safe access to keyboard
 static KB: Mutex<Keyboard> = Mutex::new(Keyboard {
     code: 0,
@@ -359,6 +380,7 @@ impl isr::ISR for KeyboardISR {
         if key.valid() {
             let ascii = key.get_ascii() as u8;
             LAST_KEY.store(ascii, Ordering::SeqCst);
+            Scheduler::wake_all(&KEY_WAITERS);
 
             //   cga::setpos(10, 10);
             //    cga::print_byte(k);
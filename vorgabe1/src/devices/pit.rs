@@ -9,7 +9,6 @@
 #![allow(dead_code)]
 
 use alloc::boxed::Box;
-use core::ptr;
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use crate::devices::cga;
@@ -30,6 +29,14 @@ pub fn get_systime() -> u64 {
 const PORT_CTRL: u16 = 0x43;
 const PORT_DATA0: u16 = 0x40;
 
+// Laenge eines Ticks in ms (siehe 'plugin')
+pub const MS_PER_TICK: u64 = 10;
+
+// convert milliseconds into ticks, rounded up
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    (ms + MS_PER_TICK - 1) / MS_PER_TICK
+}
+
 // system time ticks (each 10ms one incremented)
 static SYS_TIME: AtomicU64 = AtomicU64::new(0);
 
@@ -62,7 +69,7 @@ pub fn interval(x: u32) {
             `d` duration in ms
 */
 pub fn plugin() {
-    interval(10000); // configure 10ms
+    interval((MS_PER_TICK * 1000) as u32); // configure 10ms
     int_dispatcher::register(int_dispatcher::INT_VEC_TIMER, Box::new(PitISR));
     pic::allow(pic::IRQ_TIMER);
 }
@@ -91,16 +98,26 @@ impl isr::ISR for PitISR {
         }
 
         // We try to switch to the next thread
-        let (mut now, mut then) = (ptr::null_mut(), ptr::null_mut());
-        {
-            // Scheduler might be locked, in that case we give up preemption
-            let maybe_guard = SCHEDULER.try_lock();
-            if maybe_guard.is_some() {
-                // check if we can switch, and if yes, 'prepare_preempt' will update
-                // the status information of the scheduler
-                (now, then) = maybe_guard.unwrap().prepare_preempt();
+        let (now, then) = {
+            // The scheduler is only locked with interrupts disabled, so it is
+            // always free here. Threads holding a 'KMutex' (e.g. of a process)
+            // are preempted as usual, threads waiting for it are blocked.
+            let guard = SCHEDULER.try_lock();
+            if guard.is_none() {
+                panic!("PIT: scheduler locked with interrupts enabled");
             }
-        }
+            let mut sched = guard.unwrap();
+
+            // charge this tick to the running thread
+            sched.account_tick();
+
+            // wake up all threads whose sleep time has expired
+            sched.wake_sleepers(get_systime());
+
+            // check if we can switch, and if yes, 'prepare_preempt' will update
+            // the status information of the scheduler
+            sched.prepare_preempt()
+        };
         if !now.is_null() && !then.is_null() {
             // everything worked, so now we switch
             thread::Thread::switch(now, then);
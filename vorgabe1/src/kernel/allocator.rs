@@ -11,20 +11,31 @@
    ║                                                                         ║
    ║         Remarks                                                         ║
    ║            - Lowest loading address for grub is 1 MB                    ║
+   ║            - Requests go through the slab allocator ('slab.rs'), the    ║
+   ║              list heap ('list.rs') only provides the slabs              ║
+   ║            - The allocator is locked with interrupts disabled, ISRs     ║
+   ║              (e.g. 'Scheduler::wake_one') may allocate                  ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Philipp Oppermann                                               ║
    ║         https://os.phil-opp.com/allocator-designs/                      ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 use alloc::alloc::Layout;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
 
 use crate::consts;
-use crate::kernel::allocator::list::LinkedListAllocator;
+use crate::kernel::cpu;
+use crate::kernel::allocator::slab::{SlabAllocator, SlabStats, NR_OF_CLASSES};
 
+pub mod buddy;
+#[cfg(feature = "debug_heap")]
+pub mod debug;
 pub mod list;
+pub mod slab;
 
 #[global_allocator]
-static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+static ALLOCATOR: Locked<SlabAllocator> = Locked::new(SlabAllocator::new());
 
 /**
  Description: Initialization of the allocator. Must be called early in 'startup'.
@@ -59,15 +70,62 @@ pub fn dump_free_list() {
     ALLOCATOR.lock().dump_free_list();
 }
 
+/**
+ Description: Counters of all size classes and of the large objects.
+*/
+pub fn slab_stats() -> ([SlabStats; NR_OF_CLASSES], SlabStats) {
+    ALLOCATOR.lock().get_stats()
+}
+
+/**
+ Description: Dump the counters of the slab allocator.
+*/
+pub fn dump_slab_stats() {
+    let (classes, large) = slab_stats();
+    println!("Slab allocator (size: allocs / frees / in use / pages)");
+    for s in classes.iter() {
+        println!("   {:5} B: {} / {} / {} / {}", s.size, s.allocs, s.frees, s.in_use, s.pages);
+    }
+    println!("   large:   {} / {} / {} / {}", large.allocs, large.frees, large.in_use, large.pages);
+}
+
+/**
+ Description: Check red zones and headers of all live blocks (feature 'debug_heap'),
+              a corrupted block ends in a panic.
+*/
+pub fn check_heap() {
+    #[cfg(feature = "debug_heap")]
+    ALLOCATOR.lock().get_debug_heap().check();
+}
+
+/**
+ Description: List all live heap blocks with their call sites (feature 'debug_heap'),
+              called when a process has been released (see `Process::drop`).
+*/
+pub fn dump_leaks() {
+    #[cfg(feature = "debug_heap")]
+    ALLOCATOR.lock().get_debug_heap().dump_leaks();
+    #[cfg(not(feature = "debug_heap"))]
+    println!("dump_leaks: kernel built without feature 'debug_heap'");
+}
+
 /**
  Description: A wrapper around spin::Mutex to permit trait implementations
               Required for implementing `GlobalAlloc` in `bump.rs` and
              `list.rs`. Can be used for debugging the heap allocator.
+             Interrupts are disabled while the lock is held, otherwise an
+             ISR allocating memory would spin forever on the lock of the
+             interrupted thread.
 */
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
 }
 
+pub struct LockedGuard<'a, A> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, A>>,
+    irq: bool, // Interrupts vor 'lock' an?
+}
+
 impl<A> Locked<A> {
     pub const fn new(inner: A) -> Self {
         Locked {
@@ -75,8 +133,34 @@ impl<A> Locked<A> {
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
-        self.inner.lock()
+    pub fn lock(&self) -> LockedGuard<'_, A> {
+        let irq = cpu::disable_int_nested();
+        LockedGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            irq,
+        }
+    }
+}
+
+impl<A> Deref for LockedGuard<'_, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.guard
+    }
+}
+
+impl<A> DerefMut for LockedGuard<'_, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.guard
+    }
+}
+
+// Erst den Lock freigeben, dann die Interrupts wieder zulassen
+impl<A> Drop for LockedGuard<'_, A> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        cpu::enable_int_nested(self.irq);
     }
 }
 
@@ -0,0 +1,297 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: buddy                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Buddy allocator for page frames, used for the kernel and the    ║
+   ║         user pool (see 'frames'). Free blocks have 2^order frames       ║
+   ║         (order 0 .. MAX_ORDER) and start at a multiple of their size.   ║
+   ║         Each order has a doubly linked free list, the list nodes are    ║
+   ║         stored in the free blocks themselves.                           ║
+   ║                                                                         ║
+   ║         An allocation takes the smallest free block which is large      ║
+   ║         enough and splits it, unused frames at the end are returned     ║
+   ║         at once. On free a block is merged with its buddy (the block    ║
+   ║         'pfn ^ 2^order') as long as the buddy is free, too.             ║
+   ║                                                                         ║
+   ║         One byte per frame ('orders') tells whether a free block starts ║
+   ║         there and its order, another one ('tags') who allocated the     ║
+   ║         frame (see 'FrameOwner'), so frees are accounted per owner. The ║
+   ║         bytes are taken from the first free region which is large       ║
+   ║         enough. Nothing is zeroed here.                                 ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::boot::multiboot::PhysRegion;
+use crate::consts::PAGE_FRAME_SIZE;
+
+// Groesste Ordnung: Bloecke mit 2^MAX_ORDER Page-Frames (1 GiB)
+pub const MAX_ORDER: usize = 18;
+
+// Eintrag in 'orders' fuer Page-Frames, an denen kein freier Block beginnt
+const NOT_FREE: u8 = 0xff;
+
+// Anzahl unterscheidbarer Eigentuemer ('tag' bei 'alloc')
+pub const MAX_TAGS: usize = 8;
+
+// Listenknoten am Anfang eines freien Blocks (Adressen, 0 = kein Nachbar)
+struct FreeBlock {
+    next: usize,
+    prev: usize,
+}
+
+pub struct PfBuddyAllocator {
+    first_pfn: usize,                   // erster verwalteter Page-Frame
+    nr_of_frames: usize,                // Anzahl verwalteter Page-Frames ab 'first_pfn'
+    orders: *mut u8,                    // je Page-Frame: Ordnung des freien Blocks oder NOT_FREE
+    tags: *mut u8,                      // je Page-Frame: Eigentuemer, falls belegt
+    free_lists: [usize; MAX_ORDER + 1], // erster freier Block je Ordnung, 0 = leer
+    total_frames: usize,                // freie Page-Frames nach 'init'
+    free_frames: usize,
+    used_by_tag: [usize; MAX_TAGS],     // belegte Page-Frames je Eigentuemer
+}
+
+// Der Allokator wird nur ueber einen Mutex genutzt (siehe 'frames')
+unsafe impl Send for PfBuddyAllocator {}
+
+impl PfBuddyAllocator {
+    pub const fn new() -> Self {
+        PfBuddyAllocator {
+            first_pfn: 0,
+            nr_of_frames: 0,
+            orders: ptr::null_mut(),
+            tags: ptr::null_mut(),
+            free_lists: [0; MAX_ORDER + 1],
+            total_frames: 0,
+            free_frames: 0,
+            used_by_tag: [0; MAX_TAGS],
+        }
+    }
+
+    // Kleinste Ordnung, deren Bloecke 'pf_count' Page-Frames fassen
+    fn order_for(pf_count: usize) -> usize {
+        pf_count.next_power_of_two().trailing_zeros() as usize
+    }
+
+    /**
+        Description: Take over the free frames of `free` within `[pool_start, pool_end)`. \
+                     The regions are aligned to 4 KB, the end address is inclusive. \
+                     Frame 0 is never used (0 marks an empty list). Must be called once.
+    */
+    pub unsafe fn init(&mut self, free: &[PhysRegion], pool_start: usize, pool_end: usize) {
+        // Regionen auf 4 KB und den Pool zuschneiden
+        let mut regions: Vec<(usize, usize)> = Vec::new();
+        for region in free.iter() {
+            let start = (region.start as usize).next_multiple_of(PAGE_FRAME_SIZE).max(pool_start).max(PAGE_FRAME_SIZE);
+            let end = ((region.end as usize + 1) & !(PAGE_FRAME_SIZE - 1)).min(pool_end);
+            if start < end {
+                regions.push((start, end));
+            }
+        }
+        let (Some(lowest), Some(highest)) = (
+            regions.iter().map(|r| r.0).min(),
+            regions.iter().map(|r| r.1).max(),
+        ) else {
+            return;
+        };
+
+        self.first_pfn = lowest / PAGE_FRAME_SIZE;
+        self.nr_of_frames = (highest - lowest) / PAGE_FRAME_SIZE;
+
+        // Platz fuer 'orders' und 'tags' am Anfang einer ausreichend grossen Region
+        let meta_size = (2 * self.nr_of_frames).next_multiple_of(PAGE_FRAME_SIZE);
+        let region = regions
+            .iter_mut()
+            .find(|r| r.1 - r.0 >= meta_size)
+            .expect("PfBuddyAllocator::init: kein Platz fuer die Verwaltungsdaten");
+        self.orders = region.0 as *mut u8;
+        self.tags = self.orders.add(self.nr_of_frames);
+        region.0 += meta_size;
+        ptr::write_bytes(self.orders, NOT_FREE, 2 * self.nr_of_frames);
+
+        for (start, end) in regions {
+            let count = (end - start) / PAGE_FRAME_SIZE;
+            self.add_range(start, count);
+            self.free_frames += count;
+        }
+        self.total_frames = self.free_frames;
+    }
+
+    fn index(&self, addr: usize) -> usize {
+        addr / PAGE_FRAME_SIZE - self.first_pfn
+    }
+
+    fn contains(&self, addr: usize, pf_count: usize) -> bool {
+        let pfn = addr / PAGE_FRAME_SIZE;
+        pfn >= self.first_pfn && pfn + pf_count <= self.first_pfn + self.nr_of_frames
+    }
+
+    fn get_order(&self, addr: usize) -> u8 {
+        unsafe { *self.orders.add(self.index(addr)) }
+    }
+
+    fn set_order(&mut self, addr: usize, order: u8) {
+        unsafe { *self.orders.add(self.index(addr)) = order };
+    }
+
+    // Freien Block vorne in die Liste seiner Ordnung einfuegen
+    fn push(&mut self, addr: usize, order: usize) {
+        let head = self.free_lists[order];
+        unsafe {
+            ptr::write(addr as *mut FreeBlock, FreeBlock { next: head, prev: 0 });
+            if head != 0 {
+                (*(head as *mut FreeBlock)).prev = addr;
+            }
+        }
+        self.free_lists[order] = addr;
+        self.set_order(addr, order as u8);
+    }
+
+    // Freien Block aus der Liste seiner Ordnung entfernen
+    fn remove(&mut self, addr: usize, order: usize) {
+        let block = unsafe { ptr::read(addr as *const FreeBlock) };
+        if block.prev != 0 {
+            unsafe { (*(block.prev as *mut FreeBlock)).next = block.next };
+        } else {
+            self.free_lists[order] = block.next;
+        }
+        if block.next != 0 {
+            unsafe { (*(block.next as *mut FreeBlock)).prev = block.prev };
+        }
+        self.set_order(addr, NOT_FREE);
+    }
+
+    // Block freigeben und mit seinem Buddy verschmelzen, solange dieser frei ist
+    fn free_block(&mut self, mut addr: usize, mut order: usize) {
+        assert!(self.get_order(addr) == NOT_FREE, "PfBuddyAllocator: Block 0x{:x} ist bereits frei!", addr);
+
+        while order < MAX_ORDER {
+            let buddy = addr ^ (PAGE_FRAME_SIZE << order);
+            if !self.contains(buddy, 1 << order) || self.get_order(buddy) != order as u8 {
+                break;
+            }
+            self.remove(buddy, order);
+            addr = addr.min(buddy);
+            order += 1;
+        }
+        self.push(addr, order);
+    }
+
+    // Beliebigen Bereich in moeglichst grosse, alignierte Bloecke zerlegt freigeben
+    fn add_range(&mut self, mut addr: usize, mut pf_count: usize) {
+        while pf_count > 0 {
+            let pfn = addr / PAGE_FRAME_SIZE;
+            let max_by_align = if pfn == 0 { MAX_ORDER } else { pfn.trailing_zeros() as usize };
+            let max_by_count = (usize::BITS - 1 - pf_count.leading_zeros()) as usize;
+            let order = max_by_align.min(max_by_count).min(MAX_ORDER);
+
+            self.free_block(addr, order);
+            addr += PAGE_FRAME_SIZE << order;
+            pf_count -= 1 << order;
+        }
+    }
+
+    /**
+        Description: Allocate `pf_count` contiguous page frames (not zeroed) \
+                     for the owner `tag` (< MAX_TAGS).
+
+        Return: \
+               start address or null, if there is no block large enough
+    */
+    pub fn alloc(&mut self, pf_count: usize, tag: u8) -> *mut u8 {
+        assert!((tag as usize) < MAX_TAGS);
+        if pf_count == 0 || pf_count > 1 << MAX_ORDER {
+            return ptr::null_mut();
+        }
+        let order = Self::order_for(pf_count);
+        let Some(mut found) = (order..=MAX_ORDER).find(|o| self.free_lists[*o] != 0) else {
+            return ptr::null_mut();
+        };
+
+        let addr = self.free_lists[found];
+        self.remove(addr, found);
+
+        // Block halbieren, die obere Haelfte bleibt frei
+        while found > order {
+            found -= 1;
+            self.push(addr + (PAGE_FRAME_SIZE << found), found);
+        }
+
+        // Nicht benoetigte Page-Frames am Ende sofort zurueckgeben
+        let unused = (1 << order) - pf_count;
+        if unused > 0 {
+            self.add_range(addr + pf_count * PAGE_FRAME_SIZE, unused);
+        }
+        let first = self.index(addr);
+        unsafe { ptr::write_bytes(self.tags.add(first), tag, pf_count) };
+        self.used_by_tag[tag as usize] += pf_count;
+        self.free_frames -= pf_count;
+        addr as *mut u8
+    }
+
+    /**
+        Description: Free `pf_count` contiguous page frames starting at `ptr`. \
+                     They may be any part of an earlier allocation.
+    */
+    pub fn dealloc(&mut self, ptr: *mut u8, pf_count: usize) {
+        let addr = ptr as usize;
+        assert_eq!(addr % PAGE_FRAME_SIZE, 0);
+        assert!(self.contains(addr, pf_count), "PfBuddyAllocator: 0x{:x} liegt nicht im Pool!", addr);
+
+        let first = self.index(addr);
+        for i in first..first + pf_count {
+            let tag = unsafe { *self.tags.add(i) };
+            self.used_by_tag[tag as usize] -= 1;
+        }
+        self.add_range(addr, pf_count);
+        self.free_frames += pf_count;
+    }
+
+    // Eigentuemer des belegten Page-Frames 'ptr'
+    pub fn get_tag(&self, ptr: *const u8) -> u8 {
+        assert!(self.contains(ptr as usize, 1));
+        unsafe { *self.tags.add(self.index(ptr as usize)) }
+    }
+
+    pub fn get_total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn get_free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    // Groesster freier Block (in Page-Frames)
+    pub fn get_largest_free_block(&self) -> usize {
+        (0..=MAX_ORDER)
+            .rev()
+            .find(|o| self.free_lists[*o] != 0)
+            .map_or(0, |o| 1 << o)
+    }
+
+    pub fn get_used_by_tag(&self, tag: u8) -> usize {
+        self.used_by_tag[tag as usize]
+    }
+
+    // Anzahl freier Bloecke je Ordnung ausgeben
+    pub fn dump_free_lists(&self, name: &str) {
+        kprintln!(
+            "Buddy allocator '{}': {} free frames, pool 0x{:x} - 0x{:x}",
+            name,
+            self.free_frames,
+            self.first_pfn * PAGE_FRAME_SIZE,
+            (self.first_pfn + self.nr_of_frames) * PAGE_FRAME_SIZE
+        );
+        for order in 0..=MAX_ORDER {
+            let mut count = 0;
+            let mut block = self.free_lists[order];
+            while block != 0 {
+                count += 1;
+                block = unsafe { (*(block as *const FreeBlock)).next };
+            }
+            if count > 0 {
+                kprintln!("   order {:2} ({:6} frames): {} blocks", order, 1usize << order, count);
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: debug                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Debug mode of the kernel heap, only built with the feature      ║
+   ║         'debug_heap' (cargo make --profile debug-heap).                 ║
+   ║                                                                         ║
+   ║         Each allocation gets a header and red zones filled with a       ║
+   ║         canary in front of and behind the user data:                    ║
+   ║            [ .. | DebugHeader | red zone | data | red zone ]            ║
+   ║         New data is filled with ALLOC_POISON, freed blocks with         ║
+   ║         FREE_POISON, so reads of uninitialized or freed memory stand    ║
+   ║         out. 'dealloc' checks the header (double free, wrong layout)    ║
+   ║         and the canaries, every CHECK_INTERVAL allocations all live     ║
+   ║         blocks are checked. Errors end in a panic with the call site.   ║
+   ║                                                                         ║
+   ║         Freed blocks stay in a quarantine for QUARANTINE_SIZE further   ║
+   ║         frees before the allocator gets them back, so a double free is  ║
+   ║         still detected and a write after free shows up as a damaged     ║
+   ║         poison when the block leaves the quarantine.                    ║
+   ║                                                                         ║
+   ║         Live blocks are kept in a list together with the return         ║
+   ║         addresses of the allocating call chain (frame pointers, set by  ║
+   ║         the profile 'debug-heap' in 'Makefile.toml'), 'dump_leaks'      ║
+   ║         lists them.                                                     ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::alloc::Layout;
+use core::arch::asm;
+use core::{mem, ptr};
+
+use super::align_up;
+use crate::consts::KERNEL_VM_SIZE;
+use crate::kernel::paging::frames::PhysAddr;
+
+// Groesse einer Red-Zone und ihr Fuellwert
+const REDZONE_SIZE: usize = 16;
+const CANARY: u8 = 0xAB;
+
+// Fuellwerte fuer neu allozierten und freigegebenen Speicher
+const ALLOC_POISON: u8 = 0xCD;
+const FREE_POISON: u8 = 0xDD;
+
+// Zustand eines Blocks im Header
+const STATE_LIVE: u64 = 0x4c49_5645_4845_4150; // "LIVEHEAP"
+const STATE_FREED: u64 = 0x4652_4545_4845_4150; // "FREEHEAP"
+
+// Anzahl gespeicherter Ruecksprungadressen je Allokation
+const NR_OF_CALLERS: usize = 6;
+
+// Alle CHECK_INTERVAL Allokationen werden alle Bloecke geprueft
+const CHECK_INTERVAL: usize = 256;
+
+// Anzahl Freigaben, die ein Block in Quarantaene bleibt
+const QUARANTINE_SIZE: usize = 64;
+
+// Liegt vor der vorderen Red-Zone; 'next' und 'prev' werden nach der Freigabe
+// von den Listen des Allokators ueberschrieben, 'state' bleibt erhalten
+#[repr(C)]
+struct DebugHeader {
+    next: *mut DebugHeader, // Liste der belegten Bloecke
+    prev: *mut DebugHeader,
+    state: u64,
+    size: usize,  // wie angefordert
+    align: usize, // wie angefordert
+    callers: [usize; NR_OF_CALLERS],
+}
+
+const HEADER_SIZE: usize = mem::size_of::<DebugHeader>();
+
+/**
+ Description: State of the debug heap (list of live blocks and counters)
+*/
+pub struct DebugHeap {
+    live: *mut DebugHeader,
+    nr_of_live: usize,
+    live_bytes: usize,
+    allocs: usize,
+    quarantine: [*mut DebugHeader; QUARANTINE_SIZE], // freigegebene Bloecke (Ringpuffer)
+    next_slot: usize,                                // naechster Platz in 'quarantine'
+}
+
+// Abstand vom Anfang des Blocks bis zu den Daten, Vielfaches von 'align'
+fn data_offset(align: usize) -> usize {
+    align_up(HEADER_SIZE + REDZONE_SIZE, align.max(REDZONE_SIZE))
+}
+
+// Header des Blocks mit den Daten ab 'data'
+fn header_of(data: *mut u8) -> *mut DebugHeader {
+    (data as usize - REDZONE_SIZE - HEADER_SIZE) as *mut DebugHeader
+}
+
+// Ruecksprungadressen ueber die Kette der gesicherten rbp-Register sammeln
+// (nur im Kernel-Bereich, ein rbp aus dem User-Mode wird nicht verfolgt)
+fn collect_callers() -> [usize; NR_OF_CALLERS] {
+    let mut callers = [0; NR_OF_CALLERS];
+    let mut rbp: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp) };
+
+    let limit = (PhysAddr::get_max_phys_addr().raw() as usize).min(KERNEL_VM_SIZE);
+    for caller in callers.iter_mut() {
+        if rbp == 0 || rbp % 8 != 0 || rbp + 16 > limit {
+            break;
+        }
+        unsafe {
+            *caller = *((rbp + 8) as *const usize);
+            rbp = *(rbp as *const usize);
+        }
+    }
+    callers
+}
+
+// Pruefen, ob beide Red-Zones des Blocks noch unveraendert sind
+unsafe fn canaries_ok(header: *mut DebugHeader) -> bool {
+    let data = (header as usize + HEADER_SIZE + REDZONE_SIZE) as *const u8;
+    let front = data.sub(REDZONE_SIZE);
+    let back = data.add((*header).size);
+    (0..REDZONE_SIZE).all(|i| *front.add(i) == CANARY && *back.add(i) == CANARY)
+}
+
+unsafe fn report(msg: &str, data: *mut u8, header: *mut DebugHeader) -> ! {
+    kprintln!("debug heap: {} at 0x{:x}", msg, data as usize);
+    kprintln!("   size = {}, align = {}", (*header).size, (*header).align);
+    kprintln!("   allocated from: {:x?}", (*header).callers);
+    kprintln!("   detected at:    {:x?}", collect_callers());
+    panic!("debug heap: {}", msg);
+}
+
+// Poison des Blocks pruefen, er geht danach an den Allokator zurueck
+unsafe fn leave_quarantine(header: *mut DebugHeader) -> (*mut u8, Layout) {
+    let data = (header as usize + HEADER_SIZE + REDZONE_SIZE) as *mut u8;
+    let layout = Layout::from_size_align_unchecked((*header).size, (*header).align);
+    let poisoned = data.sub(REDZONE_SIZE);
+    if (0..layout.size() + 2 * REDZONE_SIZE).any(|i| *poisoned.add(i) != FREE_POISON) {
+        report("write after free", data, header);
+    }
+    (data.sub(data_offset(layout.align())), layout)
+}
+
+impl DebugHeap {
+    pub const fn new() -> Self {
+        DebugHeap {
+            live: ptr::null_mut(),
+            nr_of_live: 0,
+            live_bytes: 0,
+            allocs: 0,
+            quarantine: [ptr::null_mut(); QUARANTINE_SIZE],
+            next_slot: 0,
+        }
+    }
+
+    /**
+     Description: Layout of the underlying block for a request with `layout`.
+    */
+    pub fn outer_layout(layout: &Layout) -> Layout {
+        let size = data_offset(layout.align()) + layout.size() + REDZONE_SIZE;
+        Layout::from_size_align(size, layout.align().max(REDZONE_SIZE)).expect("debug heap: layout")
+    }
+
+    /**
+     Description: Set up header, red zones and poison in the new block `block`.
+
+     Return: \
+            address of the user data
+    */
+    pub unsafe fn on_alloc(&mut self, block: *mut u8, layout: &Layout) -> *mut u8 {
+        if block.is_null() {
+            return block;
+        }
+        let data = block.add(data_offset(layout.align()));
+        let header = header_of(data);
+        ptr::write(
+            header,
+            DebugHeader {
+                next: self.live,
+                prev: ptr::null_mut(),
+                state: STATE_LIVE,
+                size: layout.size(),
+                align: layout.align(),
+                callers: collect_callers(),
+            },
+        );
+        if !self.live.is_null() {
+            (*self.live).prev = header;
+        }
+        self.live = header;
+        self.nr_of_live += 1;
+        self.live_bytes += layout.size();
+
+        ptr::write_bytes(data.sub(REDZONE_SIZE), CANARY, REDZONE_SIZE);
+        ptr::write_bytes(data, ALLOC_POISON, layout.size());
+        ptr::write_bytes(data.add(layout.size()), CANARY, REDZONE_SIZE);
+
+        self.allocs += 1;
+        if self.allocs % CHECK_INTERVAL == 0 {
+            self.check();
+        }
+        data
+    }
+
+    /**
+     Description: Check the block with the user data at `data`, poison it and put \
+                  it into the quarantine.
+
+     Return: \
+            `Some((block, layout))` of the oldest block in the quarantine, which \
+            the allocator may reuse now, or `None`
+    */
+    pub unsafe fn on_dealloc(&mut self, data: *mut u8, layout: &Layout) -> Option<(*mut u8, Layout)> {
+        let header = header_of(data);
+        match (*header).state {
+            STATE_LIVE => {}
+            STATE_FREED => report("double free", data, header),
+            _ => report("free of an invalid pointer or corrupted header", data, header),
+        }
+        if (*header).size != layout.size() || (*header).align != layout.align() {
+            report("free with a different layout", data, header);
+        }
+        if !canaries_ok(header) {
+            report("red zone overwritten", data, header);
+        }
+
+        let (next, prev) = ((*header).next, (*header).prev);
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        if prev.is_null() {
+            self.live = next;
+        } else {
+            (*prev).next = next;
+        }
+        self.nr_of_live -= 1;
+        self.live_bytes -= layout.size();
+
+        (*header).state = STATE_FREED;
+        ptr::write_bytes(data.sub(REDZONE_SIZE), FREE_POISON, layout.size() + 2 * REDZONE_SIZE);
+
+        // Block in die Quarantaene, dafuer verlaesst sie der aelteste
+        let oldest = mem::replace(&mut self.quarantine[self.next_slot], header);
+        self.next_slot = (self.next_slot + 1) % QUARANTINE_SIZE;
+        if oldest.is_null() {
+            return None;
+        }
+        Some(leave_quarantine(oldest))
+    }
+
+    /**
+     Description: Check header and red zones of all live blocks.
+    */
+    pub fn check(&self) {
+        let mut header = self.live;
+        while !header.is_null() {
+            unsafe {
+                let data = (header as usize + HEADER_SIZE + REDZONE_SIZE) as *mut u8;
+                if (*header).state != STATE_LIVE {
+                    report("corrupted header", data, header);
+                }
+                if !canaries_ok(header) {
+                    report("red zone overwritten", data, header);
+                }
+                header = (*header).next;
+            }
+        }
+    }
+
+    /**
+     Description: List all live blocks with their call sites.
+    */
+    pub fn dump_leaks(&self) {
+        println!(
+            "Debug heap: {} live blocks, {} bytes ({} allocations in total)",
+            self.nr_of_live, self.live_bytes, self.allocs
+        );
+        let mut header = self.live;
+        while !header.is_null() {
+            unsafe {
+                let data = header as usize + HEADER_SIZE + REDZONE_SIZE;
+                println!("   0x{:x}: {} bytes, from {:x?}", data, (*header).size, (*header).callers);
+                header = (*header).next;
+            }
+        }
+    }
+}
@@ -2,6 +2,11 @@
    ║ Module: list                                                            ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Descr.: Implementing a list heap allocator.                             ║
+   ║                                                                         ║
+   ║         If no free block fits, the heap grows by at least               ║
+   ║         KERNEL_HEAP_GROW_SIZE with page frames of the kernel pool, up   ║
+   ║         to KERNEL_HEAP_MAX_SIZE in total. The regions are never given   ║
+   ║         back, the slabs cut from them stay in their caches ('slab').    ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Author: Philipp Oppermann                                               ║
    ║         https://os.phil-opp.com/allocator-designs/                      ║
@@ -9,12 +14,10 @@
 */
 
 use super::{align_up, Locked};
-use crate::{boot::multiboot::PhysRegion, consts::PAGE_FRAME_SIZE, kernel::cpu};
-use alloc::{
-    alloc::{GlobalAlloc, Layout},
-    string::String,
-    vec::Vec,
-};
+use crate::consts::{KERNEL_HEAP_GROW_SIZE, KERNEL_HEAP_MAX_SIZE, PAGE_FRAME_SIZE};
+use crate::kernel::paging::frames;
+use crate::kernel::paging::frames::{FrameOwner, PhysAddr};
+use alloc::alloc::{GlobalAlloc, Layout};
 use core::{mem, ptr};
 
 /**
@@ -30,17 +33,6 @@ struct ListNode {
     next: Option<&'static mut ListNode>,
 }
 
-enum MergeCase {
-    /// Case 1: Merge all three blocks (prior, new, and next).
-    MergeAll,
-    /// Case 2: Merge the new block with the next block.
-    MergeWithNext,
-    /// Case 3: Merge the new block with the prior block.
-    MergeWithPrior,
-    /// Case 4: No merge occurs.
-    NoMerge,
-}
-
 impl ListNode {
     // Create new ListMode on Stack
     // (must be 'const')
@@ -57,34 +49,33 @@ impl ListNode {
     fn end_addr(&self) -> usize {
         self.start_addr() + self.size
     }
+}
 
-    // Setzt die Startadresse der Node neu
-    fn set_start_addr(&mut self, new_start_addr: usize) {
-        let size = self.size;
-        let next = self.next.take();
-        unsafe {
-            let new_ptr = new_start_addr as *mut ListNode;
-            ptr::write(new_ptr, ListNode { size, next });
-            *self = ptr::read(new_ptr);
-        }
-    }
+/**
+ Description: A memory region of the heap
+*/
+#[derive(Clone, Copy)]
+struct HeapRegion {
+    start: usize,
+    end: usize,  // inklusive
+    grown: bool, // per 'grow' aus Page-Frames angelegt
 }
 
+// Maximale Anzahl an Bereichen: temporaerer Heap, Kernel-Heap und Erweiterungen
+const MAX_REGIONS: usize = 2 + KERNEL_HEAP_MAX_SIZE / KERNEL_HEAP_GROW_SIZE;
+
 /**
  Description: Metadata of the list allocator
 */
 pub struct LinkedListAllocator {
     head: ListNode,
-    heap_start: usize,
-    heap_end: usize,
-}
-
-pub struct PfListAllocator {
-    head: ListNode,
+    regions: [HeapRegion; MAX_REGIONS],
+    nr_of_regions: usize,
+    heap_size: usize, // Summe aller Bereiche
 }
 
-impl PfListAllocator {
-    // Creates an empty PfListAllocator.
+impl LinkedListAllocator {
+    // Creates an empty LinkedListAllocator.
     //
     // Must be const because needs to be evaluated at compile time
     // because it will be used for initializing the ALLOCATOR static
@@ -92,463 +83,62 @@ impl PfListAllocator {
     pub const fn new() -> Self {
         Self {
             head: ListNode::new(0),
+            regions: [HeapRegion { start: 0, end: 0, grown: false }; MAX_REGIONS],
+            nr_of_regions: 0,
+            heap_size: 0,
         }
     }
 
-    unsafe fn align_phys_regions_to_4_kb(free: &mut Vec<PhysRegion>) {
-        let mut aligned_regions = Vec::new();
-
-        for region in free.iter_mut() {
-            // Align `start` to the next multiple of 4 KB
-            region.start = (region.start + 0xFFF) & !0xFFF;
-
-            // Align `end` to the previous multiple of 4 KB
-            region.end = region.end & !0xFFF;
-
-            // Add to aligned_regions only if the region is valid
-            if region.start <= region.end {
-                // Clone the region or create a new instance and push it
-                aligned_regions.push(PhysRegion {
-                    start: region.start,
-                    end: region.end,
-                });
-            }
-        }
-        // Replace the original vector with the aligned and filtered regions
-        *free = aligned_regions;
-    }
-
     // Initialize the allocator with the given heap bounds.
     //
     // This function is unsafe because the caller must guarantee that
-    // the given heap bounds are valid. This method must be called only once.
-    pub unsafe fn init(&mut self, free: &mut Vec<PhysRegion>, kernel_frames: bool) {
-        PfListAllocator::align_phys_regions_to_4_kb(free);
-        //free.sort_by_key(|region| region.start);
-
-        for region in free {
-            if kernel_frames {
-                // Kernel-Speicherbereich: 0 - 64 MiB
-                if region.end > 0 && region.start < 64 * 1024 * 1024 {
-                    let aligned_end = region.end.min(64 * 1024 * 1024 - 1); // maybe -1 weg hier
-                    let size = aligned_end - region.start;
-
-                    if size > 0 {
-                        self.add_free_block(region.start as usize, size as usize);
-                    }
-                }
-            } else {
-                // User-Speicherbereich: ab 64 MiB
-                if region.end > 64 * 1024 * 1024 - 1 {
-                    let aligned_start = region.start.max(64 * 1024 * 1024); // Clamp start to 64 MiB
-                    let size = region.end - aligned_start;
-
-                    if size > 0 {
-                        self.add_free_block(aligned_start as usize, size as usize);
-                    }
-                }
-            }
-        }
+    // the given heap bounds are valid. May be called again to add another
+    // region (e.g. the kernel heap after the temporary heap).
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.add_region(heap_start, heap_size, false);
     }
 
-    unsafe fn add_free_block(&mut self, addr: usize, size: usize) {
-        // ensure that the freed block is capable of holding ListNode
-        //assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
-        assert!(size >= mem::size_of::<ListNode>());
-        assert_eq!(addr % PAGE_FRAME_SIZE, 0);
-
-        // create a new ListNode (on stack)
-        let node = ListNode::new(size);
-
-        // create a pointer to 'addr' of type ListNode
-        let ptr_node_to_insert = addr as *mut ListNode;
-
-        // copy content of new ListNode to 'addr'
-        ptr_node_to_insert.write(node);
-
-        let mut ptr_node_before_found_position: *mut ListNode = &mut self.head;
-
-        // --------------------- Suche Position zum einfügen -----------------------------------------------------
-        while let Some(ref mut ptr_node_after_found_position) =
-            (*ptr_node_before_found_position).next
-        {
-            let addr_of_node_after_found_position =
-                (*ptr_node_after_found_position as *const ListNode) as usize;
-            if addr_of_node_after_found_position > addr {
-                break; // Found insertion point
-            }
-            ptr_node_before_found_position = *ptr_node_after_found_position as *mut ListNode;
-        }
-
-        // --------------------- Debug: On/Off -----------------------------------------------------
-        let debug = false;
-        // --------------------- Debug: On/Off -----------------------------------------------------
-
-        if debug {
-            kprintln!(
-                "block befor found position at 0x{:x} with size {} up to addr at 0x{:x}",
-                (*ptr_node_before_found_position).start_addr(),
-                (*ptr_node_before_found_position).size,
-                (*ptr_node_before_found_position).end_addr()
-            );
-        }
-        if debug {
-            kprintln!(
-                "block to insert at 0x{:x} with size {} up to addr at 0x{:x}",
-                (*ptr_node_to_insert).start_addr(),
-                (*ptr_node_to_insert).size,
-                (*ptr_node_to_insert).end_addr()
-            );
-        }
-        if debug {
-            if let Some(ref mut ptr_node_after_found_position) =
-                (*ptr_node_before_found_position).next
-            {
-                kprintln!(
-                    "block after found position 0x{:x} with size {} up to addr at 0x{:x}",
-                    (*ptr_node_after_found_position).start_addr(),
-                    (*ptr_node_after_found_position).size,
-                    (*ptr_node_after_found_position).end_addr()
-                );
-            } else {
-                kprintln!("block after found position at: List End");
-            }
-        }
+    // Bereich eintragen und als freien Block in die Liste einfuegen
+    unsafe fn add_region(&mut self, start: usize, size: usize, grown: bool) {
+        assert!(self.nr_of_regions < MAX_REGIONS, "LinkedListAllocator: zu viele Bereiche");
+        self.add_free_block(start, size);
 
-        // --------------------- Merge in 3 verschiedenen Fällen -----------------------------------------------------
-
-        // Case 1: End of prior == Start of new && End of new == Start of next
-        // alle drei vereinen => Block auf den ptr_node_before_found_position zeigt vergrößern
-        // (*ptr_node_before_found_position).size erhöhen um size + (*ptr_node_after_found_position).size
-        // (*ptr_node_before_found_position).next auf den node setzen auf den (*ptr_node_after_found_position).next zeigt
-        // Case 2: End of new == Start of next
-        // neuen und folgenden vereinen => Block auf den ptr_node_after_found_position zeigt vergrößern und auf neue start adresse setzen
-        // (*ptr_node_after_found_position).size erhöhen um size
-        // adresse auf die ptr_node_after_found_position zeigt auf ptr_node_to_insert ändern
-        // Case 3: End of prior == Start of new
-        // neuen und vorangehenden vereinen => Block auf den ptr_node_before_found_position zeigt vergrößern
-        // (*ptr_node_before_found_position).size erhöhen um size
-        // Case 4: No merge
-
-        if let Some(ref mut ptr_node_after_found_position) = (*ptr_node_before_found_position).next
-        {
-            // Nicht am Ende einfügen => Case 1 - 4
-            let case = if (*ptr_node_before_found_position).end_addr() == (*ptr_node_to_insert).start_addr() && (*ptr_node_to_insert).end_addr() == (*ptr_node_after_found_position).start_addr(){
-                MergeCase::MergeAll                
-            } else if (*ptr_node_to_insert).end_addr() == (*ptr_node_after_found_position).start_addr(){
-                MergeCase::MergeWithNext
-            } else if (*ptr_node_before_found_position).end_addr() == (*ptr_node_to_insert).start_addr(){
-                MergeCase::MergeWithPrior
-            } else {
-                MergeCase::NoMerge
-            };
-            if debug {
-                kprintln!("#####################################");
-                kprintln!("#####################################");
-                kprintln!("end_prior  == 0x{:x}", (*ptr_node_before_found_position).end_addr());
-                kprintln!("start_new  == 0x{:x}", (*ptr_node_to_insert).start_addr());
-                kprintln!("end_new    == 0x{:x}", (*ptr_node_to_insert).end_addr());
-                kprintln!("start_next == 0x{:x}", (*ptr_node_after_found_position).start_addr());
-            }
-            match case {
-                MergeCase::MergeAll => {
-                    if debug {
-                        kprintln!(".......................");
-                        kprintln!("....add_free case 1....");
-                        kprintln!(
-                            "MergeAll: end_prior 0x{:x} == 0x{:x} start_new && end_new 0x{:x} == 0x{:x} start_next",
-                            (*ptr_node_before_found_position).end_addr(),
-                            (*ptr_node_to_insert).start_addr(),
-                            (*ptr_node_to_insert).end_addr(),
-                            (*ptr_node_after_found_position).start_addr()
-                        );
-                        kprintln!(".......................");
-                    }
-                    // case 1: Merged new block with prior one
-                    let old_size = (*ptr_node_before_found_position).size;
-                    (*ptr_node_before_found_position).size =
-                        old_size + size + (*ptr_node_after_found_position).size;
-                    (*ptr_node_before_found_position).next =
-                        (*ptr_node_after_found_position).next.take();
-
-                    if debug {
-                        kprintln!(
-                        "Case 1: Merged new block with prior and following one starting at 0x{:x}, old size: 0x{:x}, new size: 0x{:x}",
-                        (*ptr_node_before_found_position).start_addr(), old_size, (*ptr_node_before_found_position).size
-                    );
-                    }
-                }
-                MergeCase::MergeWithNext => {
-                    if debug {
-                        kprintln!(".......................");
-                        kprintln!("....add_free case 2....");
-                        kprintln!(
-                            "MergeWithNext: end_new 0x{:x} == 0x{:x} start_next",
-                            (*ptr_node_to_insert).end_addr(),
-                            (*ptr_node_after_found_position).start_addr()
-                        );
-                        kprintln!(".......................");
-                    }
-                    // case 2: Merged new block with prior one
-                    let old_size = (*ptr_node_before_found_position).size;
-                    (*ptr_node_before_found_position).size = old_size + size;
-
-                    if debug {
-                        kprintln!(
-                        "Case 2: Merged new block with prior one starting at 0x{:x}, old size: 0x{:x}, new size: 0x{:x}",
-                        (*ptr_node_before_found_position).start_addr(), old_size, (*ptr_node_before_found_position).size
-                    );
-                    }
-                }
-                MergeCase::MergeWithPrior => {
-                    if debug {
-                        kprintln!(".........................");
-                        kprintln!("....add_free case 3.1....");
-                        kprintln!(
-                            "MergeWithPrior: end_prior 0x{:x} == 0x{:x} start_new",
-                            (*ptr_node_before_found_position).end_addr(),
-                            (*ptr_node_to_insert).start_addr()
-                        );
-                        kprintln!(".........................");
-                    }
-                    // case 3: Merged new block with post one
-                    let old_size = (*ptr_node_after_found_position).size;
-                    (*ptr_node_after_found_position).size = old_size + size;
-                    ptr_node_after_found_position.set_start_addr(addr);
-
-                    if debug {
-                        kprintln!(
-                        "Case 3: Merged new block with following one starting at 0x{:x}, old size: 0x{:x}, new size: 0x{:x}",
-                        (*ptr_node_before_found_position).start_addr(), old_size, (*ptr_node_before_found_position).size
-                    );
-                    }
-                }
-                MergeCase::NoMerge => {
-                    if debug {
-                        kprintln!(".................................");
-                        kprintln!("....add_free case 4.1 NoMerge....");
-                        kprintln!(".................................");
-                    }
-                    // case 4: no merge
-                    // Insert the new node
-                    (*ptr_node_to_insert).next = (*ptr_node_before_found_position).next.take(); // Link the new node to the next node
-                    (*ptr_node_before_found_position).next = Some(&mut *ptr_node_to_insert); // Link the current node to the new node
-                    if debug {
-                        kprintln!("no merge inserted between above mentioned blocks");
-                    }
-                }
-            }
-        } else {
-            if debug {
-                kprintln!("#####################################");
-                kprintln!("#####################################");
-                kprintln!("end_prior  == 0x{:x}", (*ptr_node_before_found_position).end_addr());
-                kprintln!("start_new  == 0x{:x}", (*ptr_node_to_insert).start_addr());
-                kprintln!("end_new    == 0x{:x}", (*ptr_node_to_insert).end_addr());
-                kprintln!("start_next == List End");
-            }
-            // Am Ende einfügen => Case 3 und 4
-            if (*ptr_node_before_found_position).end_addr() == (*ptr_node_to_insert).start_addr() {
-                if debug {
-                    kprintln!(".........................");
-                    kprintln!("....add_free case 3.2....");
-                    kprintln!(
-                        "MergeWithPrior: end_prior 0x{:x} == 0x{:x} start_new",
-                        (*ptr_node_before_found_position).end_addr(),
-                        (*ptr_node_to_insert).start_addr()
-                    );
-                    kprintln!(".........................");
-                }
-                // case 2: Merged new block with prior one
-                let old_size = (*ptr_node_before_found_position).size;
-                (*ptr_node_before_found_position).size = old_size + size;
-
-                if debug {
-                    kprintln!(
-                    "Case 3: Merged new block with prior one starting at 0x{:x}, old size: 0x{:x}, new size: 0x{:x}",
-                    (*ptr_node_before_found_position).start_addr(), old_size, (*ptr_node_before_found_position).size
-                );
-                }
-            } else {
-                if debug {
-                    kprintln!(".................................");
-                    kprintln!("....add_free case 4.2 NoMerge....");
-                    kprintln!(".................................");
-                }
-                // case 4: no merge
-                // Insert the new node
-                (*ptr_node_to_insert).next = (*ptr_node_before_found_position).next.take(); // Link the new node to the next node
-                (*ptr_node_before_found_position).next = Some(&mut *ptr_node_to_insert); // Link the current node to the new node
-                if debug {
-                    kprintln!("no merge inserted between above mentioned blocks");
-                }
-            }
-        }
-        if debug {
-            kprintln!("#####################################");
-            kprintln!("#####################################");
-        }
+        self.regions[self.nr_of_regions] = HeapRegion {
+            start,
+            end: start + size - 1,
+            grown,
+        };
+        self.nr_of_regions += 1;
+        self.heap_size += size;
     }
 
-    // Search a free block with the given size and alignment and remove
-    // it from the free list.
-    //
-    // Return: 'ListNode' or 'None'
-    fn find_free_block(&mut self, pf_count: usize) -> Option<&'static mut ListNode> {
-        // 4KB times pf_count
-        let size = pf_count * PAGE_FRAME_SIZE;
-        // reference to current list node, updated for each iteration
-        let mut current = &mut self.head;
-
-        // search for a large enough memory block in the linked list
-        // save next block in 'block' (may be 'None' -> use 'Some')
-        while let Some(ref mut block) = current.next {
-            // check if current 'block' is large enough
-            if let Ok(alloc_start) = Self::check_block_for_alloc(&block, size) {
-                let next = block.next.take(); // save successor of 'block'
-                let ret = Some(current.next.take().unwrap()); // take 'block'
-                current.next = next; // set 'next' to successor of 'block'
-                return ret;
-            } else {
-                // block too small -> continue with next block
-                current = current.next.as_mut().unwrap();
-            }
-        }
-        // no suitable block found
-        None
+    // Liegt 'ptr' in einem der Bereiche dieses Heaps?
+    pub fn contains(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        self.regions[..self.nr_of_regions]
+            .iter()
+            .any(|r| addr >= r.start && addr <= r.end)
     }
 
-    // Check if the given 'block' is large enough for an allocation with
+    // Heap um mindestens 'size' Bytes mit Alignment 'align' vergroessern
     //
-    // Return: OK(allocation start address) or Err
-    fn check_block_for_alloc(block: &ListNode, size: usize) -> Result<usize, ()> {
-        let alloc_start = block.start_addr(); //align_up(block.start_addr());
-
-        let alloc_end = match alloc_start.checked_add(size) {
-            Some(end) => end, // unused but required by compiler
-            None => return Err(()),
-        };
-
-        // block too small?
-        if alloc_end > block.end_addr() {
-            return Err(());
+    // Return: false, falls die Page-Frames noch nicht verwaltet werden,
+    //         KERNEL_HEAP_MAX_SIZE erreicht ist oder keine Frames frei sind
+    unsafe fn grow(&mut self, size: usize, align: usize) -> bool {
+        if !frames::pf_is_ready() {
+            return false;
         }
-
-        // rest of block too small to hold a ListNode (required because the
-        // allocation splits the block in a used and a free part)
-        let remaining_block_size = block.end_addr() - alloc_end;
-        if remaining_block_size > 0 && remaining_block_size < mem::size_of::<ListNode>() {
-            return Err(());
+        let bytes = align_up(size + align, PAGE_FRAME_SIZE).max(KERNEL_HEAP_GROW_SIZE);
+        if self.heap_size + bytes > KERNEL_HEAP_MAX_SIZE || self.nr_of_regions == MAX_REGIONS {
+            return false;
         }
 
-        // block suitable for allocation
-        Ok(alloc_start)
-    }
-
-    // Dump free list
-    pub fn dump_free_list(&mut self, input_string: String) {
-        kprintln!(
-            "Dumping free memory list PFListAllocator (including dummy element): {}",
-            input_string
-        );
-
-        // reference to current list node, updated for each iteration
-        let mut current = &mut self.head;
-
-        // Walk through linked list
-        while let Some(ref mut block) = current.next {
-            kprintln!(
-                "   Block start:  0x{:x}, block end: 0x{:x}, block size: 0x{:x}, 4kb block num: {}",
-                block.start_addr(),
-                block.start_addr() + block.size,
-                block.size,
-                block.size / PAGE_FRAME_SIZE
-            );
-
-            // continue with next block
-            current = current.next.as_mut().unwrap();
+        let start = frames::pf_alloc_uninit(bytes / PAGE_FRAME_SIZE, true, FrameOwner::KernelHeap);
+        if start == PhysAddr(0) {
+            return false;
         }
-    }
-
-    // soll pf_count viele 4kb blöcke allozieren
-    // wird entweder auf dem Kernel Space oder User-Space aufgerufen
-    pub unsafe fn alloc(&mut self, pf_count: usize) -> *mut u8 {
-        // perform layout adjustments
-        let ret_ptr: *mut u8;
-
-        if let Some(block) = self.find_free_block(pf_count) {
-            let alloc_end = block
-                .start_addr()
-                .checked_add(pf_count * PAGE_FRAME_SIZE)
-                .expect("overflow");
-
-            // the remaining memory will be inserted as new block
-            // the size is large enough to store metadata; this is
-            // checked in 'check_block_for_alloc' called by 'find_free_block'
-            let remaining_block_size = block.end_addr() - alloc_end;
-            if remaining_block_size > 0 {
-                self.add_free_block(alloc_end, remaining_block_size);
-                /*kprintln!(
-                    "remaining block at addr=0x{:x} with size 0x{:x} ({} 4kb frames remaining)",
-                    alloc_end,
-                    remaining_block_size,
-                    remaining_block_size / PAGE_FRAME_SIZE
-                );*/
-            }
-            // gefunden freien block zürck geben
-            ret_ptr = block.start_addr() as *mut u8;
-
-            // Neuen Block mit 0en initialisieren (langsam)
-            if !ret_ptr.is_null() {
-                let slice = unsafe { core::slice::from_raw_parts_mut(ret_ptr, pf_count * PAGE_FRAME_SIZE) };
-                slice.fill(0);
-            }
-
-            kprintln!(
-                "allocated block from addr=0x{:x} till addr=0x{:x} with size 0x{:x} and {} Blocks",
-                block.start_addr(),
-                alloc_end - 1,
-                pf_count * PAGE_FRAME_SIZE,
-                pf_count
-            );
-        } else {
-            // println!(", *** out of memory ***");
-            ret_ptr = ptr::null_mut(); // out of memory
-        }
-        ret_ptr
-    }
-
-    pub unsafe fn dealloc(&mut self, ptr: *mut u8, pf_count: usize) {
-        //kprintln!("   dealloc: size={}, align={}; not supported", layout.size(), layout.align());
-        assert_eq!(ptr as usize % PAGE_FRAME_SIZE, 0);
-        let size = pf_count * PAGE_FRAME_SIZE;
-        self.add_free_block(ptr as usize, size)
-    }
-}
-
-impl LinkedListAllocator {
-    // Creates an empty LinkedListAllocator.
-    //
-    // Must be const because needs to be evaluated at compile time
-    // because it will be used for initializing the ALLOCATOR static
-    // see 'allocator.rs'
-    pub const fn new() -> Self {
-        Self {
-            head: ListNode::new(0),
-            heap_start: 0,
-            heap_end: 0,
-        }
-    }
-
-    // Initialize the allocator with the given heap bounds.
-    //
-    // This function is unsafe because the caller must guarantee that
-    // the given heap bounds are valid. This method must be called only once.
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
-        self.add_free_block(heap_start, heap_size);
-
-        self.heap_start = heap_start;
-        self.heap_end = heap_start + heap_size - 1;
+        self.add_region(start.to_start_address(), bytes, true);
+        true
     }
 
     // Adds the given free memory block 'addr' to the front of the free list.
@@ -576,8 +166,8 @@ impl LinkedListAllocator {
     // Search a free block with the given size and alignment and remove
     // it from the free list.
     //
-    // Return: 'ListNode' or 'None'
-    fn find_free_block(&mut self, size: usize, align: usize) -> Option<&'static mut ListNode> {
+    // Return: ('ListNode', allocation start address) or 'None'
+    fn find_free_block(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
         // reference to current list node, updated for each iteration
         let mut current = &mut self.head;
 
@@ -587,7 +177,7 @@ impl LinkedListAllocator {
             // check if current 'block' is large enough
             if let Ok(alloc_start) = Self::check_block_for_alloc(&block, size, align) {
                 let next = block.next.take(); // save successor of 'block'
-                let ret = Some(current.next.take().unwrap()); // take 'block'
+                let ret = Some((current.next.take().unwrap(), alloc_start)); // take 'block'
                 current.next = next; // set 'next' to successor of 'block'
                 return ret;
             } else {
@@ -604,7 +194,13 @@ impl LinkedListAllocator {
     //
     // Return: OK(allocation start address) or Err
     fn check_block_for_alloc(block: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
-        let alloc_start = align_up(block.start_addr(), align);
+        let mut alloc_start = align_up(block.start_addr(), align);
+
+        // the part in front of the allocation stays free and must hold a ListNode
+        let padding = alloc_start - block.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            alloc_start = align_up(block.start_addr() + mem::size_of::<ListNode>(), align);
+        }
 
         let alloc_end = match alloc_start.checked_add(size) {
             Some(end) => end, // unused but required by compiler
@@ -643,10 +239,14 @@ impl LinkedListAllocator {
     // Dump free list
     pub fn dump_free_list(&mut self) {
         println!("Dumping free memory list (including dummy element)");
-        println!(
-            "   Heap start:   0x{:x}, heap end:  0x{:x}",
-            self.heap_start, self.heap_end
-        );
+        for r in self.regions[..self.nr_of_regions].iter() {
+            println!(
+                "   Heap start:   0x{:x}, heap end:  0x{:x}{}",
+                r.start,
+                r.end,
+                if r.grown { " (grown)" } else { "" }
+            );
+        }
 
         // reference to current list node, updated for each iteration
         let mut current = &mut self.head;
@@ -672,17 +272,27 @@ impl LinkedListAllocator {
         let (size, align) = LinkedListAllocator::size_align(layout);
         let ret_ptr: *mut u8;
 
-        if let Some(block) = self.find_free_block(size, align) {
-            let alloc_end = block.start_addr().checked_add(size).expect("overflow");
+        // kein passender Block -> Heap vergroessern und erneut suchen
+        let mut found = self.find_free_block(size, align);
+        if found.is_none() && self.grow(size, align) {
+            found = self.find_free_block(size, align);
+        }
+
+        if let Some((block, alloc_start)) = found {
+            let (block_start, block_end) = (block.start_addr(), block.end_addr());
+            let alloc_end = alloc_start.checked_add(size).expect("overflow");
 
-            // the remaining memory will be inserted as new block
-            // the size is large enough to store metadata; this is
-            // checked in 'check_block_for_alloc' called by 'find_free_block'
-            let remaining_block_size = block.end_addr() - alloc_end;
+            // the memory in front of and behind the allocation will be
+            // inserted as new blocks; both are large enough to store metadata,
+            // this is checked in 'check_block_for_alloc' called by 'find_free_block'
+            if alloc_start > block_start {
+                self.add_free_block(block_start, alloc_start - block_start);
+            }
+            let remaining_block_size = block_end - alloc_end;
             if remaining_block_size > 0 {
                 self.add_free_block(alloc_end, remaining_block_size);
             }
-            ret_ptr = block.start_addr() as *mut u8;
+            ret_ptr = alloc_start as *mut u8;
             //   kprintln!(", returning addr=0x{:x}", block.start_addr());
         } else {
             // println!(", *** out of memory ***");
@@ -694,7 +304,7 @@ impl LinkedListAllocator {
     pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
         //kprintln!("   dealloc: size={}, align={}; not supported", layout.size(), layout.align());
         let (size, _) = LinkedListAllocator::size_align(layout);
-        self.add_free_block(ptr as usize, size)
+        self.add_free_block(ptr as usize, size);
     }
 }
 
@@ -0,0 +1,256 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: slab                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Slab allocator in front of the list heap, used as the global    ║
+   ║         allocator (see 'allocator').                                    ║
+   ║                                                                         ║
+   ║         Requests up to MAX_CLASS_SIZE are rounded up to a size class    ║
+   ║         (16 B, 32 B, .. 2 KiB). Each class has a cache with a free list ║
+   ║         of objects; if it is empty, a 4 KB slab is taken from the list  ║
+   ║         heap and cut into objects. Alloc and dealloc are thus O(1) and  ║
+   ║         the list heap is only walked for new slabs. Slabs are never     ║
+   ║         given back, freed objects stay in their cache.                  ║
+   ║                                                                         ║
+   ║         Larger objects (e.g. kernel stacks) get page frames of the      ║
+   ║         kernel pool ('frames'). Before 'pf_init' they come from the     ║
+   ║         list heap, too.                                                 ║
+   ║                                                                         ║
+   ║         With the feature 'debug_heap' every request is wrapped by       ║
+   ║         'DebugHeap' (red zones, poisoning, quarantine, leak report).    ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+#[cfg(feature = "debug_heap")]
+use super::debug::DebugHeap;
+use super::list::LinkedListAllocator;
+use super::Locked;
+use crate::consts::PAGE_FRAME_SIZE;
+use crate::kernel::paging::frames;
+use crate::kernel::paging::frames::{FrameOwner, PhysAddr};
+
+// Groessenklassen: MIN_CLASS_SIZE << index, bis MAX_CLASS_SIZE
+pub const MIN_CLASS_SIZE: usize = 16;
+pub const MAX_CLASS_SIZE: usize = 2048;
+pub const NR_OF_CLASSES: usize = 8;
+
+// Groesse einer Slab
+const SLAB_SIZE: usize = PAGE_FRAME_SIZE;
+
+/**
+ Description: Counters of one size class or of the large objects
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlabStats {
+    pub size: usize,   // Objektgroesse der Klasse (0 = grosse Objekte)
+    pub allocs: usize, // Anzahl Allokationen insgesamt
+    pub frees: usize,  // Anzahl Freigaben insgesamt
+    pub in_use: usize, // derzeit belegte Objekte
+    pub pages: usize,  // Slabs der Klasse bzw. Page-Frames grosser Objekte
+}
+
+impl SlabStats {
+    const fn new(size: usize) -> Self {
+        SlabStats {
+            size,
+            allocs: 0,
+            frees: 0,
+            in_use: 0,
+            pages: 0,
+        }
+    }
+}
+
+// Freies Objekt in einer Slab (Liste liegt in den Objekten selbst)
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct SlabCache {
+    free: *mut FreeObject,
+    stats: SlabStats,
+}
+
+/**
+ Description: Metadata of the slab allocator
+*/
+pub struct SlabAllocator {
+    heap: LinkedListAllocator,
+    caches: [SlabCache; NR_OF_CLASSES],
+    large: SlabStats,
+    #[cfg(feature = "debug_heap")]
+    debug: DebugHeap,
+}
+
+// Der Allokator wird nur ueber 'Locked' genutzt
+unsafe impl Send for SlabAllocator {}
+
+impl SlabAllocator {
+    // Must be const because it is used for initializing the ALLOCATOR static
+    pub const fn new() -> Self {
+        const EMPTY: SlabCache = SlabCache {
+            free: ptr::null_mut(),
+            stats: SlabStats::new(0),
+        };
+        let mut caches = [EMPTY; NR_OF_CLASSES];
+        let mut i = 0;
+        while i < NR_OF_CLASSES {
+            caches[i].stats.size = MIN_CLASS_SIZE << i;
+            i += 1;
+        }
+        SlabAllocator {
+            heap: LinkedListAllocator::new(),
+            caches,
+            large: SlabStats::new(0),
+            #[cfg(feature = "debug_heap")]
+            debug: DebugHeap::new(),
+        }
+    }
+
+    // Add the memory block as region of the list heap (see 'LinkedListAllocator::init')
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap.init(heap_start, heap_size);
+    }
+
+    pub fn dump_free_list(&mut self) {
+        self.heap.dump_free_list();
+    }
+
+    // Index der Groessenklasse fuer 'layout', 'None' fuer grosse Objekte
+    fn class_index(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align()).max(MIN_CLASS_SIZE).next_power_of_two();
+        if size > MAX_CLASS_SIZE {
+            return None;
+        }
+        Some((size / MIN_CLASS_SIZE).trailing_zeros() as usize)
+    }
+
+    // Neue Slab vom List-Heap holen und in Objekte der Klasse 'index' zerlegen
+    unsafe fn grow(&mut self, index: usize) -> bool {
+        let slab = self.heap.alloc(Layout::from_size_align_unchecked(SLAB_SIZE, SLAB_SIZE));
+        if slab.is_null() {
+            return false;
+        }
+
+        let cache = &mut self.caches[index];
+        let size = cache.stats.size;
+        for i in (0..SLAB_SIZE / size).rev() {
+            let obj = slab.add(i * size) as *mut FreeObject;
+            (*obj).next = cache.free;
+            cache.free = obj;
+        }
+        cache.stats.pages += 1;
+        true
+    }
+
+    pub unsafe fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "debug_heap")]
+        {
+            let block = self.alloc_inner(DebugHeap::outer_layout(&layout));
+            self.debug.on_alloc(block, &layout)
+        }
+        #[cfg(not(feature = "debug_heap"))]
+        self.alloc_inner(layout)
+    }
+
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "debug_heap")]
+        {
+            // Der Block geht in Quarantaene, freigegeben wird ein aelterer
+            if let Some((block, layout)) = self.debug.on_dealloc(ptr, &layout) {
+                self.dealloc_inner(block, DebugHeap::outer_layout(&layout));
+            }
+        }
+        #[cfg(not(feature = "debug_heap"))]
+        self.dealloc_inner(ptr, layout);
+    }
+
+    #[cfg(feature = "debug_heap")]
+    pub fn get_debug_heap(&self) -> &DebugHeap {
+        &self.debug
+    }
+
+    unsafe fn alloc_inner(&mut self, layout: Layout) -> *mut u8 {
+        let Some(index) = Self::class_index(&layout) else {
+            return self.alloc_large(layout);
+        };
+        if self.caches[index].free.is_null() && !self.grow(index) {
+            return ptr::null_mut(); // out of memory
+        }
+
+        let cache = &mut self.caches[index];
+        let obj = cache.free;
+        cache.free = (*obj).next;
+        cache.stats.allocs += 1;
+        cache.stats.in_use += 1;
+        obj as *mut u8
+    }
+
+    // Grosses Objekt aus Page-Frames (bzw. vor 'pf_init' vom List-Heap)
+    unsafe fn alloc_large(&mut self, layout: Layout) -> *mut u8 {
+        let ret_ptr = if frames::pf_is_ready() {
+            let pf_count = layout.size().div_ceil(PAGE_FRAME_SIZE);
+            let align_count = layout.align().div_ceil(PAGE_FRAME_SIZE);
+            let addr = if align_count > 1 {
+                frames::pf_alloc_aligned(pf_count, align_count, true, FrameOwner::KernelHeap, false)
+            } else {
+                frames::pf_alloc_uninit(pf_count, true, FrameOwner::KernelHeap)
+            };
+            if addr != PhysAddr(0) {
+                self.large.pages += pf_count;
+            }
+            addr.as_mut_ptr()
+        } else {
+            self.heap.alloc(layout)
+        };
+
+        if !ret_ptr.is_null() {
+            self.large.allocs += 1;
+            self.large.in_use += 1;
+        }
+        ret_ptr
+    }
+
+    unsafe fn dealloc_inner(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(index) = Self::class_index(&layout) else {
+            if self.heap.contains(ptr) {
+                self.heap.dealloc(ptr, layout);
+            } else {
+                let pf_count = layout.size().div_ceil(PAGE_FRAME_SIZE);
+                frames::pf_free(PhysAddr::new(ptr as u64), pf_count);
+                self.large.pages -= pf_count;
+            }
+            self.large.frees += 1;
+            self.large.in_use -= 1;
+            return;
+        };
+
+        let cache = &mut self.caches[index];
+        let obj = ptr as *mut FreeObject;
+        (*obj).next = cache.free;
+        cache.free = obj;
+        cache.stats.frees += 1;
+        cache.stats.in_use -= 1;
+    }
+
+    // Zaehler aller Groessenklassen und der grossen Objekte
+    pub fn get_stats(&self) -> ([SlabStats; NR_OF_CLASSES], SlabStats) {
+        let mut classes = [SlabStats::default(); NR_OF_CLASSES];
+        for (stats, cache) in classes.iter_mut().zip(self.caches.iter()) {
+            *stats = cache.stats;
+        }
+        (classes, self.large)
+    }
+}
+
+// Trait required by the Rust runtime for heap allocations
+unsafe impl GlobalAlloc for Locked<SlabAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().dealloc(ptr, layout);
+    }
+}
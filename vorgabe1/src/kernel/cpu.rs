@@ -121,6 +121,17 @@ pub fn halt() {
     }
 }
 
+/**
+ Description: enable interrupts and stop CPU until the next interrupt. \
+              Returns after the interrupt has been handled.
+*/
+#[inline]
+pub fn wait_for_int() {
+    unsafe {
+        asm!("sti; hlt");
+    }
+}
+
 /**
  Description: return RFLAGS
 */
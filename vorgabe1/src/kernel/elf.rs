@@ -0,0 +1,296 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: elf                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Loader for static ELF64 executables (x86_64), e.g. user         ║
+   ║         programs loaded by GRUB as multiboot modules. Each program      ║
+   ║         gets a new process; its PT_LOAD segments are mapped into the    ║
+   ║         user part of the address space and the first thread starts in   ║
+   ║         ring 3 at the ELF entry point.                                  ║
+   ║                                                                         ║
+   ║         Segments must lie between KERNEL_VM_SIZE and the user stacks    ║
+   ║         (USER_STACKS_VM_BOTTOM) and must not share a page with another  ║
+   ║         segment. Bytes beyond 'p_filesz' (.bss) are zero, because       ║
+   ║         'pf_alloc' returns zeroed page frames. The heap of the process  ║
+   ║         starts right after the highest segment.                         ║
+   ║                                                                         ║
+   ║         More information about ELF can be found here:                   ║
+   ║         https://refspecs.linuxfoundation.org/elf/gabi4+/contents.html   ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::mem::size_of;
+use core::ptr;
+
+use crate::consts::KERNEL_VM_SIZE;
+use crate::consts::PAGE_SIZE;
+use crate::consts::USER_STACKS_VM_BOTTOM;
+use crate::kernel::paging::frames::FrameOwner;
+use crate::kernel::paging::pages;
+use crate::kernel::paging::pages::PageSize;
+use crate::kernel::threads::kmutex::KMutex;
+use crate::kernel::threads::process::{Backing, Process, VmArea, VmKind, VmProt};
+use crate::kernel::threads::thread::Thread;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 0x3e;
+
+// Typen und Flags der Program-Header
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+// ELF-Header (Anfang der Datei)
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Elf64Header {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+// Program-Header, beschreibt ein Segment
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Elf64ProgramHeader {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+// Gruende, warum ein Programm nicht geladen werden kann
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ElfError {
+    NotElf,           // Magic fehlt
+    Not64Bit,         // kein ELFCLASS64
+    NotLittleEndian,  // kein ELFDATA2LSB
+    WrongMachine,     // nicht x86_64
+    NotExecutable,    // kein statisches Executable (ET_EXEC)
+    Truncated,        // Header oder Segment liegt ausserhalb der Datei
+    BadSegment,       // Segment ausserhalb des User-Bereichs, 'p_filesz' > 'p_memsz'
+                      // oder teilt sich eine Seite mit einem anderen Segment
+}
+
+// Struktur vom Typ 'T' an 'offset' aus 'image' lesen (nicht aligniert)
+fn read_struct<T: Copy>(image: &[u8], offset: u64) -> Result<T, ElfError> {
+    let offset = offset as usize;
+    let end = offset.checked_add(size_of::<T>()).ok_or(ElfError::Truncated)?;
+    if end > image.len() {
+        return Err(ElfError::Truncated);
+    }
+    Ok(unsafe { ptr::read_unaligned(image.as_ptr().add(offset) as *const T) })
+}
+
+// ELF-Header lesen und pruefen
+fn read_header(image: &[u8]) -> Result<Elf64Header, ElfError> {
+    if image.len() < 4 || image[0..4] != ELF_MAGIC {
+        return Err(ElfError::NotElf);
+    }
+    let header: Elf64Header = read_struct(image, 0)?;
+    if header.e_ident[4] != ELFCLASS64 {
+        return Err(ElfError::Not64Bit);
+    }
+    if header.e_ident[5] != ELFDATA2LSB {
+        return Err(ElfError::NotLittleEndian);
+    }
+    if header.e_machine != EM_X86_64 {
+        return Err(ElfError::WrongMachine);
+    }
+    if header.e_type != ET_EXEC {
+        return Err(ElfError::NotExecutable);
+    }
+    if (header.e_phentsize as usize) < size_of::<Elf64ProgramHeader>() {
+        return Err(ElfError::Truncated);
+    }
+    Ok(header)
+}
+
+// i-ten Program-Header lesen
+fn read_program_header(image: &[u8], header: &Elf64Header, i: u16) -> Result<Elf64ProgramHeader, ElfError> {
+    let offset = header
+        .e_phoff
+        .checked_add(i as u64 * header.e_phentsize as u64)
+        .ok_or(ElfError::Truncated)?;
+    read_struct(image, offset)
+}
+
+// PT_LOAD-Segment pruefen, bevor etwas gemappt wird
+fn check_segment(image: &[u8], ph: &Elf64ProgramHeader) -> Result<(), ElfError> {
+    if ph.p_filesz > ph.p_memsz {
+        return Err(ElfError::BadSegment);
+    }
+    let file_end = ph.p_offset.checked_add(ph.p_filesz).ok_or(ElfError::Truncated)?;
+    if file_end > image.len() as u64 {
+        return Err(ElfError::Truncated);
+    }
+    let vm_end = ph.p_vaddr.checked_add(ph.p_memsz).ok_or(ElfError::BadSegment)?;
+    if ph.p_vaddr < KERNEL_VM_SIZE as u64 || vm_end > USER_STACKS_VM_BOTTOM as u64 {
+        return Err(ElfError::BadSegment);
+    }
+    Ok(())
+}
+
+// Seitengrenzen [start, end) eines geprueften Segments
+fn segment_pages(ph: &Elf64ProgramHeader) -> (usize, usize) {
+    let start = ph.p_vaddr as usize & !(PAGE_SIZE - 1);
+    let end = (ph.p_vaddr + ph.p_memsz) as usize;
+    (start, end.div_ceil(PAGE_SIZE) * PAGE_SIZE)
+}
+
+// Rechte eines Segments aus den Flags im Program-Header
+fn segment_prot(p_flags: u32) -> VmProt {
+    let mut prot = VmProt::empty();
+    prot.set(VmProt::READ, p_flags & PF_R != 0);
+    prot.set(VmProt::WRITE, p_flags & PF_W != 0);
+    prot.set(VmProt::EXEC, p_flags & PF_X != 0);
+    prot
+}
+
+// Daten eines Segments seitenweise in den Adressraum von 'process' kopieren.
+// Die Page-Frames sind identisch im Kernel gemappt.
+fn copy_segment(process: &Process, image: &[u8], ph: &Elf64ProgramHeader) {
+    let pml4_addr = process.get_pml4_addr();
+    let mut copied: usize = 0;
+
+    while copied < ph.p_filesz as usize {
+        let vm_addr = ph.p_vaddr as usize + copied;
+        let chunk = (PAGE_SIZE - vm_addr % PAGE_SIZE).min(ph.p_filesz as usize - copied);
+        let phys = pages::pg_translate(pml4_addr, vm_addr).expect("copy_segment: Seite nicht gemappt");
+        unsafe {
+            ptr::copy_nonoverlapping(
+                image.as_ptr().add(ph.p_offset as usize + copied),
+                phys.as_mut_ptr::<u8>(),
+                chunk,
+            );
+        }
+        copied += chunk;
+    }
+}
+
+/**
+    Description: Map the PT_LOAD segments of `image` into the user address space of `process`. \
+                 All headers are checked before anything is mapped; segments \
+                 sharing a page are rejected, as each page has only one set of rights.
+
+    Parameters: \
+           `process` process which gets the program (no threads yet) \
+           `image` complete ELF file
+
+    Return: \
+           entry point of the program or the reason why it was rejected
+*/
+pub fn load(process: &Arc<KMutex<Process>>, image: &[u8]) -> Result<u64, ElfError> {
+    let header = read_header(image)?;
+
+    // Erst alle Segmente pruefen. Segmente duerfen sich keine Seite teilen, sonst
+    // wuerden ihre Rechte in einem PTE zusammenfallen (z.B. W+X) und sich die VMAs
+    // ueberlappen.
+    for i in 0..header.e_phnum {
+        let ph = read_program_header(image, &header, i)?;
+        if ph.p_type != PT_LOAD {
+            continue;
+        }
+        check_segment(image, &ph)?;
+        if ph.p_memsz == 0 {
+            continue;
+        }
+        let (start, end) = segment_pages(&ph);
+        for j in 0..i {
+            let other = read_program_header(image, &header, j)?;
+            if other.p_type != PT_LOAD || other.p_memsz == 0 {
+                continue;
+            }
+            let (other_start, other_end) = segment_pages(&other);
+            if start < other_end && other_start < end {
+                return Err(ElfError::BadSegment);
+            }
+        }
+    }
+
+    let mut p = process.lock();
+    let mut image_end = 0;
+    for i in 0..header.e_phnum {
+        let ph = read_program_header(image, &header, i)?;
+        if ph.p_type != PT_LOAD || ph.p_memsz == 0 {
+            continue;
+        }
+
+        let (start, pages_end) = segment_pages(&ph);
+        let end = (ph.p_vaddr + ph.p_memsz) as usize;
+        let nr_of_pages = (pages_end - start) / PAGE_SIZE;
+
+        kprintln!(
+            "elf::load: segment [0x{:x}, 0x{:x}) {}{}{}",
+            ph.p_vaddr,
+            end,
+            if ph.p_flags & PF_R != 0 { "r" } else { "-" },
+            if ph.p_flags & PF_W != 0 { "w" } else { "-" },
+            if ph.p_flags & PF_X != 0 { "x" } else { "-" }
+        );
+
+        pages::pg_mmap_user_range(
+            p.get_pml4_addr(),
+            start,
+            nr_of_pages,
+            ph.p_flags & PF_W != 0,
+            ph.p_flags & PF_X != 0,
+            FrameOwner::UserPage,
+        );
+        copy_segment(&p, image, &ph);
+        p.add_mapping(VmArea {
+            start,
+            len: nr_of_pages * PAGE_SIZE,
+            kind: VmKind::Image,
+            prot: segment_prot(ph.p_flags),
+            backing: Backing::Eager,
+            page_size: PageSize::Size4K,
+        });
+        image_end = image_end.max(end);
+    }
+
+    // Heap beginnt hinter dem Programm
+    if image_end != 0 {
+        p.set_heap_start(image_end);
+    }
+
+    Ok(header.e_entry)
+}
+
+/**
+    Description: Load the program `image` into a new process and create its first thread.
+
+    Parameters: \
+           `image` complete ELF file, e.g. a multiboot module
+
+    Return: \
+           user thread starting at the ELF entry point, must be passed to `Scheduler::ready`
+*/
+pub fn load_program(image: &[u8]) -> Result<Box<Thread>, ElfError> {
+    // Header pruefen, bevor ein Adressraum angelegt wird
+    read_header(image)?;
+
+    let process = Process::new();
+    let entry = load(&process, image)?;
+
+    Ok(Thread::new_user_program(&process, entry))
+}
@@ -15,6 +15,7 @@
 use crate::devices::kprint;
 use crate::kernel::cpu;
 use crate::kernel::interrupts::isr;
+use crate::kernel::paging::page_fault;
 use alloc::{boxed::Box, vec::Vec};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
@@ -207,23 +208,20 @@ pub extern "C" fn int_gpf(error_code: u64, cs: u16, rip: u64) {
 
 /** Blatt 4 Aufgabe 1
 Description:
-   Handling a page fault. Called from assembly 'interrupts.asm'
+   Handling a page fault. Called from assembly 'interrupts.asm'. \
+   Returns only if the fault has been resolved (see 'page_fault').
 
 Parameters: \
+   `error_code`  see x86 spec. \
+   `cr2`         virtual address which caused the PF \
    `rip`         address of the instruction which caused the PF \
-   `cs`          active 'cs' when PF occurred \
-   `error_code`  see x86 spec.
+   `rip_slot`    saved rip on the stack, may be changed to continue elsewhere
 */
 #[no_mangle]
-pub extern "C" fn int_pf(error_code: u64, cs: u16, rip: u64) {
-    // force unlock, just to be sure
-    // anyway we do not return
-    unsafe {
-        kprint::WRITER.force_unlock();
+pub extern "C" fn int_pf(error_code: u64, cr2: u64, rip: u64, rip_slot: *mut u64) {
+    if let Some(fixup) = page_fault::handle(error_code, cr2, rip) {
+        unsafe {
+            *rip_slot = fixup;
+        }
     }
-    kprintln!(
-        "page fault: caused by function at 0x{:x}. (error_code = 0x{:x})",
-        rip, error_code
-    );
-    loop {}
 }
@@ -57,7 +57,10 @@ _wrapper_%1:
 		mov    rdi, [rsp+15*8] ; error code
 		mov    rdx, [rsp+16*8] ; rip
 		mov    rsi, cr2 ; cr2
+		lea    rcx, [rsp+16*8] ; Adresse von rip, fuer 'user_copy::fixup'
+		sub    rsp, 8   ; error code -> stack is not 16 byte aligned
 	    call    int_pf
+		add    rsp, 8
    %else
    	  	; pass the vector as parameter 
 		xor rax, rax
@@ -83,6 +86,12 @@ _wrapper_%1:
    	pop    rbx
 	pop    rax
 
+	; the page fault handler may return (resolved fault),
+	; remove the error code pushed by the CPU
+	%if %1 == 14
+		add    rsp, 8
+	%endif
+
 	; done!
   	iretq
 %endmacro
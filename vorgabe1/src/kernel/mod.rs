@@ -1,6 +1,8 @@
 pub mod allocator;
 pub mod cpu;
+pub mod elf;
 pub mod interrupts;
 pub mod threads;
 pub mod syscall;
-pub mod paging;
\ No newline at end of file
+pub mod paging;
+pub mod shm;
@@ -3,45 +3,103 @@
  *                  F R A M E S                                              *
  *                                                                           *
  *---------------------------------------------------------------------------*
- * Beschreibung:    Verwaltung der Page-Frames in zwei Listen:               *
+ * Beschreibung:    Verwaltung der Page-Frames in zwei Pools:                *
  *                     - Kernel-Page-Frames: 0 .. 64 MiB - 1                 *
  *                     - User-Page-Frames:   >= 64 MiB                       *
- *                  Der Code ist eine angepasste Version des ListAllocators. *
- *                  Wir verwalten hier auch Speicherbloecke, deren Start-    *
- *                  Adresse aber immer 4 KB aliginiert sind und deren Groesse*
- *                  immer 4 KB oder ein Vielfaches davon sind. Zudem werden  *
- *                  die Metadaten direkt in dem freien Page-Frame gespeichert*
- *                  und die Liste ist aufsteigend sortiert nach den          *
- *                  Startadressen der Bloecke. Durch die Sortierung ist eine *
- *                  Verschmelzung bei der Freigabe einfach moeglich.         *
+ *                  Jeder Pool ist ein Buddy-Allokator (siehe 'buddy'), eine *
+ *                  Allokation oder Freigabe kostet damit nur O(log n).      *
+ *                  'pf_alloc' liefert genullte Page-Frames, Aufrufer die    *
+ *                  den Inhalt sofort komplett ueberschreiben, nutzen        *
+ *                  'pf_alloc_uninit' (z.B. Kopie einer COW-Seite).          *
+ *                  Jede Allokation nennt ihren Eigentuemer ('FrameOwner'),  *
+ *                  'pf_stats' liefert damit Zaehler je Pool und Eigentuemer.*
  *                                                                           *
  * Autor:           Michael Schoettner, 21.1.2024                            *
  *****************************************************************************/
 
-use core::num;
 use core::ops::Add;
-use core::slice;
-use core::{mem, ptr};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use alloc::alloc::Layout;
-use alloc::string::ToString;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use spin::Mutex;
 
 use crate::boot::multiboot::PhysRegion;
 use crate::consts::KERNEL_PHYS_SIZE;
 use crate::consts::PAGE_FRAME_SIZE;
-use crate::devices::kprint;
-use crate::kernel::allocator::list::PfListAllocator;
+use crate::kernel::cpu;
+use crate::kernel::allocator::buddy::{PfBuddyAllocator, MAX_TAGS};
 
 // letzte nutzbare physikalische Adresse
 // (notwendig fuer das 1:1 mapping des Kernels in den Page-Tables)
 static mut MAX_PHYS_ADDR: PhysAddr = PhysAddr(0);
 
-// Page-Frames > KERNEL_VM_SIZE
-static mut FREE_USER_PAGE_FRAMES: PfListAllocator = PfListAllocator::new();
+// true, sobald 'pf_init' fertig ist (der Kernel-Heap nutzt die Pools erst dann)
+static PF_READY: AtomicBool = AtomicBool::new(false);
 
-// Page-Frames 0 .. KERNEL_VM_SIZE - 1
-static mut FREE_KERNEL_PAGE_FRAMES: PfListAllocator = PfListAllocator::new();
+// Page-Frames >= KERNEL_PHYS_SIZE
+static FREE_USER_PAGE_FRAMES: Mutex<PfBuddyAllocator> = Mutex::new(PfBuddyAllocator::new());
+
+// Page-Frames 0 .. KERNEL_PHYS_SIZE - 1
+static FREE_KERNEL_PAGE_FRAMES: Mutex<PfBuddyAllocator> = Mutex::new(PfBuddyAllocator::new());
+
+// Wofuer Page-Frames alloziert wurden (Statistik, siehe 'pf_stats')
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameOwner {
+    KernelHeap = 0, // Kernel-Heap (inkl. Kernel-Stacks)
+    PageTable,      // Page-Tables aller Adressraeume
+    UserPage,       // Seiten von User-Prozessen (Image, Heap, mmap)
+    UserStack,      // User-Stacks
+    Shm,            // Shared-Memory-Objekte
+}
+
+const NR_OF_OWNERS: usize = 5;
+const _: () = assert!(NR_OF_OWNERS <= MAX_TAGS);
+
+impl FrameOwner {
+    fn from_tag(tag: u8) -> Self {
+        match tag {
+            0 => FrameOwner::KernelHeap,
+            1 => FrameOwner::PageTable,
+            2 => FrameOwner::UserPage,
+            3 => FrameOwner::UserStack,
+            _ => FrameOwner::Shm,
+        }
+    }
+}
+
+// Zaehler eines Pools, alle Angaben in Page-Frames
+// (wird auch per Systemaufruf in den User-Mode kopiert)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    pub total: u64,         // nach 'pf_init' verfuegbar
+    pub free: u64,
+    pub largest_free: u64,  // groesster zusammenhaengender freier Block
+    pub fragmentation: u64, // in Prozent: Anteil freier Frames ausserhalb des groessten Blocks
+}
+
+// Speicherstatistik, siehe 'pf_stats' (alle Angaben in Page-Frames)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemStats {
+    pub kernel_pool: PoolStats,
+    pub user_pool: PoolStats,
+    pub kernel_heap: u64,
+    pub page_tables: u64,
+    pub user_pages: u64,
+    pub user_stacks: u64,
+    pub shm: u64,
+    pub process_pages: u64,  // vom aufrufenden Prozess eingeblendet (nur per Systemaufruf)
+    pub process_tables: u64, // Page-Tables des aufrufenden Prozesses (nur per Systemaufruf)
+}
+
+// Referenzzaehler fuer Page-Frames, die in mehreren Adressraeumen gemappt sind
+// (z.B. nach 'fork'). Eingetragen sind nur Frames mit mehr als einer Referenz,
+// Schluessel ist die Adresse des ersten Frames eines Blocks (bei 2 MB Seiten).
+static FRAME_REFS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
 
 // Eine physikalische Adresse
 #[derive(Clone, Copy, PartialEq, PartialOrd, Ord, Eq)]
@@ -98,9 +156,9 @@ impl Add<PhysAddr> for PhysAddr {
 }
 
 
-// Initialisiert die Page-Frame-Liste anhand der uebergebenen freien Memory-Regionen
-// Bei Bedarf werden die Memory-Regionen angepasst, sodass die Startadresse
-// 4 KB aliginiert ist und auch die Grösse 4 KB oder ein Vielfaches davon ist
+// Initialisiert beide Pools anhand der uebergebenen freien Memory-Regionen
+// Die Regionen werden dabei auf 4 KB aligniert, die Verwaltungsdaten der
+// Pools liegen in den ersten Page-Frames einer passenden Region
 pub fn pf_init(free: &mut Vec<PhysRegion>) {
     unsafe {
         // MAX_PHYS_ADDR setzen
@@ -111,49 +169,201 @@ pub fn pf_init(free: &mut Vec<PhysRegion>) {
             }
         }
         kprintln!("pf_init: max phys addr = 0x{:x}", MAX_PHYS_ADDR.raw());
-        FREE_USER_PAGE_FRAMES.init(free, false);   
-        FREE_USER_PAGE_FRAMES.dump_free_list("user".to_string());  
-        FREE_KERNEL_PAGE_FRAMES.init(free, true);     
-        FREE_KERNEL_PAGE_FRAMES.dump_free_list("kernel".to_string());
+        FREE_USER_PAGE_FRAMES.lock().init(free, KERNEL_PHYS_SIZE, usize::MAX);
+        FREE_KERNEL_PAGE_FRAMES.lock().init(free, 0, KERNEL_PHYS_SIZE);
     }
+    PF_READY.store(true, Ordering::SeqCst);
+    pf_dump_lists();
+}
+
+// Koennen schon Page-Frames alloziert werden?
+pub fn pf_is_ready() -> bool {
+    PF_READY.load(Ordering::SeqCst)
 }
 
 pub fn pf_dump_lists(){
-    unsafe {
-        FREE_USER_PAGE_FRAMES.dump_free_list("user".to_string());  
-        FREE_KERNEL_PAGE_FRAMES.dump_free_list("kernel".to_string());
+    let irq = cpu::disable_int_nested();
+    FREE_USER_PAGE_FRAMES.lock().dump_free_lists("user");
+    FREE_KERNEL_PAGE_FRAMES.lock().dump_free_lists("kernel");
+    cpu::enable_int_nested(irq);
+}
+
+// Zaehler eines Pools ermitteln
+fn pool_stats(pool: &PfBuddyAllocator) -> PoolStats {
+    let free = pool.get_free_frames() as u64;
+    let largest_free = pool.get_largest_free_block().min(pool.get_free_frames()) as u64;
+    PoolStats {
+        total: pool.get_total_frames() as u64,
+        free,
+        largest_free,
+        fragmentation: if free == 0 { 0 } else { 100 - largest_free * 100 / free },
     }
 }
 
+// Zaehler beider Pools und belegte Page-Frames je Eigentuemer
+// ('process_pages' und 'process_tables' bleiben 0, siehe 'Process::count_frames')
+pub fn pf_stats() -> MemStats {
+    let irq = cpu::disable_int_nested();
+    let kernel = FREE_KERNEL_PAGE_FRAMES.lock();
+    let user = FREE_USER_PAGE_FRAMES.lock();
+    let used = |owner: FrameOwner| (kernel.get_used_by_tag(owner as u8) + user.get_used_by_tag(owner as u8)) as u64;
+    let stats = MemStats {
+        kernel_pool: pool_stats(&kernel),
+        user_pool: pool_stats(&user),
+        kernel_heap: used(FrameOwner::KernelHeap),
+        page_tables: used(FrameOwner::PageTable),
+        user_pages: used(FrameOwner::UserPage),
+        user_stacks: used(FrameOwner::UserStack),
+        shm: used(FrameOwner::Shm),
+        process_pages: 0,
+        process_tables: 0,
+    };
+    drop(user);
+    drop(kernel);
+    cpu::enable_int_nested(irq);
+    stats
+}
+
+// Statistik ausgeben, z.B. um nach dem Ende von Threads Lecks zu finden
+pub fn pf_dump_stats() {
+    let s = pf_stats();
+    for (name, p) in [("kernel", s.kernel_pool), ("user", s.user_pool)] {
+        kprintln!(
+            "pf_stats: {:6} pool: {} of {} frames free, largest block {}, fragmentation {}%",
+            name, p.free, p.total, p.largest_free, p.fragmentation
+        );
+    }
+    kprintln!(
+        "pf_stats: heap {}, page tables {}, user pages {}, user stacks {}, shm {}",
+        s.kernel_heap, s.page_tables, s.user_pages, s.user_stacks, s.shm
+    );
+}
 
-// Alloziere 'pf_count' aufeinanderfolgende Page-Frames
+// Pool fuer Kernel- oder User-Page-Frames
+fn pool(in_kernel_space: bool) -> &'static Mutex<PfBuddyAllocator> {
+    if in_kernel_space {
+        &FREE_KERNEL_PAGE_FRAMES
+    } else {
+        &FREE_USER_PAGE_FRAMES
+    }
+}
+
+// Alloziere 'pf_count' aufeinanderfolgende Page-Frames fuer 'owner' ohne sie zu nullen
 // Vom Kernel-Space, falls 'in_kernel_space' = true
 // Oder User-Space, falls 'in_kernel_space' = false
-pub fn pf_alloc(pf_count: usize, in_kernel_space: bool) -> PhysAddr {
-    unsafe {
-        if in_kernel_space {
-            // Kernel-Space alloc
-            //kprintln!("allocate {} pages with each size 4kb (size: {} bytes) in kernel space", pf_count, pf_count*PAGE_FRAME_SIZE);
-            PhysAddr::new(FREE_KERNEL_PAGE_FRAMES.alloc(pf_count) as u64)
-        } else {
-            // User-Space alloc
-            //kprintln!("allocate {} pages with each size 4kb (size: {} bytes) in user space", pf_count, pf_count*PAGE_FRAME_SIZE);
-            PhysAddr::new(FREE_USER_PAGE_FRAMES.alloc(pf_count) as u64)
-        }
+// Rueckgabe PhysAddr(0), falls kein ausreichend grosser Block frei ist
+pub fn pf_alloc_uninit(pf_count: usize, in_kernel_space: bool, owner: FrameOwner) -> PhysAddr {
+    let irq = cpu::disable_int_nested();
+    let addr = pool(in_kernel_space).lock().alloc(pf_count, owner as u8);
+    cpu::enable_int_nested(irq);
+    PhysAddr::new(addr as u64)
+}
+
+// Wie 'pf_alloc_uninit', die Page-Frames sind aber genullt
+// (das Nullen erfolgt ausserhalb der Sperre)
+pub fn pf_alloc(pf_count: usize, in_kernel_space: bool, owner: FrameOwner) -> PhysAddr {
+    let addr = pf_alloc_uninit(pf_count, in_kernel_space, owner);
+    if addr != PhysAddr(0) {
+        unsafe { ptr::write_bytes(addr.as_mut_ptr::<u8>(), 0, pf_count * PAGE_FRAME_SIZE) };
     }
+    addr
+}
+
+// Alloziere 'pf_count' aufeinanderfolgende Page-Frames, deren Startadresse ein
+// Vielfaches von 'align_count' Page-Frames ist (z.B. 512 fuer eine 2 MB Seite).
+// Ist 'align_count' eine Zweierpotenz >= 'pf_count', liefert der Buddy-Allokator
+// passende Bloecke direkt, sonst wird mehr alloziert und der nicht benoetigte
+// Anfang und Rest wieder freigegeben. Genullt wird nur, falls 'zero' = true.
+pub fn pf_alloc_aligned(
+    pf_count: usize,
+    align_count: usize,
+    in_kernel_space: bool,
+    owner: FrameOwner,
+    zero: bool,
+) -> PhysAddr {
+    let align = (align_count * PAGE_FRAME_SIZE) as u64;
+    let total = if align_count.is_power_of_two() && align_count >= pf_count {
+        align_count
+    } else {
+        pf_count + align_count - 1
+    };
+
+    let block = pf_alloc_uninit(total, in_kernel_space, owner);
+    if block == PhysAddr(0) {
+        return block;
+    }
+    let start = block.raw().next_multiple_of(align);
+    let head = ((start - block.raw()) / PAGE_FRAME_SIZE as u64) as usize;
+    let tail = total - head - pf_count;
+
+    if head > 0 {
+        pf_free(block, head);
+    }
+    if tail > 0 {
+        pf_free(PhysAddr::new(start + (pf_count * PAGE_FRAME_SIZE) as u64), tail);
+    }
+    if zero {
+        unsafe { ptr::write_bytes(start as *mut u8, 0, pf_count * PAGE_FRAME_SIZE) };
+    }
+    PhysAddr::new(start)
 }
 
 // Gebe 'pf_count' aufeinanderfolgende Page-Frames frei
 // Zuordnung User- oder Kernel-Space ergibt sich anhand der Adresse
 pub fn pf_free(pf_addr: PhysAddr, pf_count: usize) {
-    unsafe{
-        let ptr_u8 = pf_addr.raw() as *mut u8;
-        if pf_addr.raw() < 64 * 1024 * 1024 { // kernel dealloc
-            kprintln!("deallocate {} pages with each size 4kb (space: {} bytes) in kernel space at address=0x{:x}", pf_count, pf_count*PAGE_FRAME_SIZE, pf_addr.raw());
-            FREE_KERNEL_PAGE_FRAMES.dealloc(ptr_u8, pf_count);
-        } else { // user dealloc
-            kprintln!("deallocate {} pages with each size 4kb (space: {} bytes) in user space at address=0x{:x}", pf_count, pf_count*PAGE_FRAME_SIZE, pf_addr.raw());
-            FREE_USER_PAGE_FRAMES.dealloc(ptr_u8, pf_count);
+    let irq = cpu::disable_int_nested();
+    pool((pf_addr.raw() as usize) < KERNEL_PHYS_SIZE)
+        .lock()
+        .dealloc(pf_addr.as_mut_ptr(), pf_count);
+    cpu::enable_int_nested(irq);
+}
+
+// Eigentuemer des belegten Page-Frames 'pf_addr'
+pub fn pf_owner(pf_addr: PhysAddr) -> FrameOwner {
+    let irq = cpu::disable_int_nested();
+    let tag = pool((pf_addr.raw() as usize) < KERNEL_PHYS_SIZE)
+        .lock()
+        .get_tag(pf_addr.as_ptr());
+    cpu::enable_int_nested(irq);
+    FrameOwner::from_tag(tag)
+}
+
+// Eine weitere Referenz auf den Block ab 'pf_addr' eintragen, der Block wird
+// erst mit der letzten 'pf_release' freigegeben
+pub fn pf_share(pf_addr: PhysAddr) {
+    let irq = cpu::disable_int_nested();
+    *FRAME_REFS.lock().entry(pf_addr.raw()).or_insert(1) += 1;
+    cpu::enable_int_nested(irq);
+}
+
+// Anzahl der Referenzen auf den Block ab 'pf_addr' (1 = nicht geteilt)
+pub fn pf_ref_count(pf_addr: PhysAddr) -> usize {
+    let irq = cpu::disable_int_nested();
+    let count = FRAME_REFS.lock().get(&pf_addr.raw()).copied().unwrap_or(1);
+    cpu::enable_int_nested(irq);
+    count
+}
+
+// Eine Referenz auf den Block mit 'pf_count' Page-Frames ab 'pf_addr' aufgeben.
+// War es die letzte, wird der Block freigegeben.
+pub fn pf_release(pf_addr: PhysAddr, pf_count: usize) {
+    let irq = cpu::disable_int_nested();
+    let shared = {
+        let mut refs = FRAME_REFS.lock();
+        match refs.get_mut(&pf_addr.raw()) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 1 {
+                    refs.remove(&pf_addr.raw());
+                }
+                true
+            }
+            None => false,
         }
+    };
+    cpu::enable_int_nested(irq);
+
+    if !shared {
+        pf_free(pf_addr, pf_count);
     }
 }
@@ -1,2 +1,3 @@
 pub mod pages;
-pub mod frames;
\ No newline at end of file
+pub mod frames;
+pub mod page_fault;
\ No newline at end of file
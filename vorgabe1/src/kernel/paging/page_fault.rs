@@ -0,0 +1,248 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: page_fault                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Page-fault handling, called by 'int_pf' (see 'int_dispatcher'). ║
+   ║         The faulting address (CR2) is looked up in the mappings of the  ║
+   ║         process of the running thread. A first touch of a lazily-backed ║
+   ║         area ('Backing::Lazy') is resolved by mapping a zeroed page     ║
+   ║         frame (or 2 MB page), the faulting instruction is restarted.    ║
+   ║         A write to a copy-on-write page (see 'fork') gets its own copy  ║
+   ║         of the page frame, also if the kernel writes to a user buffer.  ║
+   ║                                                                         ║
+   ║         Any other fault in ring 3 kills only the offending thread with  ║
+   ║         'EXIT_PAGE_FAULT'. A fault in ring 0 is a kernel bug -> panic.  ║
+   ║         A fault of the kernel while copying a user buffer continues at  ║
+   ║         the fixup of 'user_copy', the copy then returns an error.       ║
+   ║                                                                         ║
+   ║         If another thread holds the process lock, the faulting thread   ║
+   ║         blocks until it is free (see 'KMutex'). If the faulting thread  ║
+   ║         holds it itself, the fault cannot be resolved.                  ║
+   ║                                                                         ║
+   ║         A touch of the guard page below a user stack is reported as a   ║
+   ║         stack overflow, an instruction fetch from a page without EXEC   ║
+   ║         (NX bit, e.g. stack or heap) as an execute violation.           ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::fmt;
+
+use crate::kernel::paging::frames::FrameOwner;
+use crate::kernel::paging::pages;
+use crate::kernel::paging::pages::PageSize;
+use crate::kernel::syscall::user_copy;
+use crate::kernel::threads::process::{Backing, VmKind, VmProt};
+use crate::kernel::threads::scheduler;
+
+// Exit-Code eines Threads, der wegen eines Page-Faults beendet wurde
+pub const EXIT_PAGE_FAULT: i64 = -14;
+
+// Bits im Error-Code eines Page-Faults (Intel SDM Vol. 3, 4.7)
+const PF_PRESENT: u64 = 1 << 0; // 0 = Seite nicht present, 1 = Schutzverletzung
+const PF_WRITE: u64 = 1 << 1; // 0 = Lesen, 1 = Schreiben
+const PF_USER: u64 = 1 << 2; // 0 = Ring 0, 1 = Ring 3
+const PF_RESERVED: u64 = 1 << 3; // reserviertes Bit in einem Eintrag gesetzt
+const PF_INSTR_FETCH: u64 = 1 << 4; // Befehl holen
+
+// Dekodierter Page-Fault
+#[derive(Clone, Copy)]
+pub struct PageFault {
+    pub error_code: u64,
+    pub addr: usize, // CR2
+    pub rip: u64,
+}
+
+impl PageFault {
+    pub fn new(error_code: u64, cr2: u64, rip: u64) -> Self {
+        PageFault {
+            error_code,
+            addr: cr2 as usize,
+            rip,
+        }
+    }
+
+    // Schutzverletzung auf einer vorhandenen Seite (sonst: Seite nicht present)
+    pub fn is_protection_violation(&self) -> bool {
+        self.error_code & PF_PRESENT != 0
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.error_code & PF_WRITE != 0
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.error_code & PF_USER != 0
+    }
+
+    pub fn is_reserved_bit(&self) -> bool {
+        self.error_code & PF_RESERVED != 0
+    }
+
+    pub fn is_instr_fetch(&self) -> bool {
+        self.error_code & PF_INSTR_FETCH != 0
+    }
+}
+
+impl fmt::Display for PageFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = if self.is_instr_fetch() {
+            "execute"
+        } else if self.is_write() {
+            "write"
+        } else {
+            "read"
+        };
+        let reason = if self.is_reserved_bit() {
+            "reserved bit set"
+        } else if self.is_protection_violation() {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        write!(
+            f,
+            "{} {} access to 0x{:x} ({}), rip = 0x{:x}, error_code = 0x{:x}",
+            if self.is_user() { "user" } else { "kernel" },
+            access,
+            self.addr,
+            reason,
+            self.rip,
+            self.error_code
+        )
+    }
+}
+
+// Warum ein Page-Fault nicht aufgeloest werden konnte
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FaultError {
+    NoProcess,                    // kein laufender Thread oder Scheduler gerade gesperrt
+    ProcessLocked,                // Prozess vom fehlerhaften Thread selbst gesperrt
+    NoMapping,                    // Adresse liegt in keinem Bereich des Prozesses
+    Protection,                   // Seite ist vorhanden, Zugriff aber nicht erlaubt
+    ReservedBit,                  // defekter Seitentabelleneintrag
+    AccessDenied,                 // Zugriffsart ist im Bereich nicht erlaubt (siehe 'VmProt')
+    NotLazy,                      // Seite fehlt in einem sofort unterlegten Bereich
+    NoExecute,                    // Befehl aus einer nicht ausfuehrbaren Seite geholt (NX)
+    StackOverflow { tid: usize }, // Guard-Page unter dem Stack von 'tid'
+}
+
+/**
+    Description: Try to resolve `fault` in the address space of the running thread.
+
+    Return: \
+           `Ok` if a page frame has been mapped (or a copy-on-write page has been \
+           copied) and the access can be restarted
+*/
+pub fn resolve(fault: &PageFault) -> Result<(), FaultError> {
+    if fault.is_reserved_bit() {
+        return Err(FaultError::ReservedBit);
+    }
+    // Seite ist vorhanden, aber nicht ausfuehrbar -> nicht aufloesbar
+    if fault.is_instr_fetch() && fault.is_protection_violation() {
+        return Err(FaultError::NoExecute);
+    }
+
+    // Haelt ein anderer Thread den Prozess, warten wir auf ihn. Haelt ihn der
+    // Thread selbst, wuerde 'lock' nie zurueckkehren (Fehler im Kernel, beim
+    // Zugriff auf User-Speicher darf der Prozess nicht gesperrt sein)
+    let process = scheduler::get_active_process().ok_or(FaultError::NoProcess)?;
+    if process.owner() == Some(scheduler::get_active_tid()) {
+        return Err(FaultError::ProcessLocked);
+    }
+    let p = process.lock();
+
+    let area = *p.find_mapping(fault.addr).ok_or(FaultError::NoMapping)?;
+    if let VmKind::StackGuard { tid } = area.kind {
+        return Err(FaultError::StackOverflow { tid });
+    }
+    let needed = if fault.is_instr_fetch() {
+        VmProt::EXEC
+    } else if fault.is_write() {
+        VmProt::WRITE
+    } else {
+        VmProt::READ
+    };
+    if !area.prot.contains(needed) {
+        if fault.is_instr_fetch() {
+            return Err(FaultError::NoExecute);
+        }
+        return Err(FaultError::AccessDenied);
+    }
+
+    // Schreiben auf eine mit 'fork' geteilte Seite -> eigene Kopie anlegen
+    if fault.is_protection_violation() {
+        if fault.is_write() && pages::pg_resolve_cow(p.get_pml4_addr(), fault.addr) {
+            return Ok(());
+        }
+        return Err(FaultError::Protection);
+    }
+    if area.backing != Backing::Lazy {
+        return Err(FaultError::NotLazy);
+    }
+
+    // Erster Zugriff -> genullten Page-Frame einblenden (bzw. 2 MB bei MAP_HUGETLB)
+    let writeable = area.prot.contains(VmProt::WRITE);
+    let executable = area.prot.contains(VmProt::EXEC);
+    let page = fault.addr & !(area.page_size.bytes() - 1);
+    let owner = match area.kind {
+        VmKind::Stack { .. } => FrameOwner::UserStack,
+        _ => FrameOwner::UserPage,
+    };
+    if area.page_size == PageSize::Size4K {
+        pages::pg_mmap_user_range(p.get_pml4_addr(), page, 1, writeable, executable, owner);
+    } else {
+        pages::pg_mmap_user_huge_page(p.get_pml4_addr(), page, writeable, executable, owner);
+    }
+    Ok(())
+}
+
+/**
+    Description: Page-fault handler. Returns only if the fault has been resolved \
+                 or if the kernel was copying a user buffer.
+
+    Parameters: \
+           `error_code` see x86 spec. \
+           `cr2`        virtual address which caused the PF \
+           `rip`        address of the instruction which caused the PF
+
+    Return: \
+           `None` to restart the instruction, otherwise the address to continue at
+*/
+pub fn handle(error_code: u64, cr2: u64, rip: u64) -> Option<u64> {
+    let fault = PageFault::new(error_code, cr2, rip);
+
+    let err = match resolve(&fault) {
+        Ok(()) => return None,
+        Err(err) => err,
+    };
+
+    // Kernel kopiert einen User-Puffer ('copy_from_user', 'copy_to_user')
+    if !fault.is_user() {
+        if let Some(fixup) = user_copy::fixup(fault.rip) {
+            kprintln!("page fault: user copy failed: {} ({:?})", fault, err);
+            return Some(fixup);
+        }
+    }
+
+    if let FaultError::StackOverflow { tid } = err {
+        kprintln!("page fault: stack overflow of tid={} at 0x{:x}, rip = 0x{:x}", tid, fault.addr, fault.rip);
+    }
+    if err == FaultError::NoExecute {
+        kprintln!(
+            "page fault: {} instruction fetch from non-executable page 0x{:x}",
+            if fault.is_user() { "user" } else { "kernel" },
+            fault.addr
+        );
+    }
+
+    // Nur den fehlerhaften User-Thread beenden
+    if fault.is_user() {
+        kprintln!(
+            "page fault: killing tid={}: {} ({:?})",
+            scheduler::get_active_tid(),
+            fault,
+            err
+        );
+        scheduler::Scheduler::exit(EXIT_PAGE_FAULT);
+    }
+
+    panic!("page fault in kernel: {} ({:?})", fault, err);
+}
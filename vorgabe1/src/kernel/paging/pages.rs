@@ -9,25 +9,47 @@
  *****************************************************************************/
 
 use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::fmt;
 use core::ops::BitOr;
 use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use x86;
 
 use crate::consts::KERNEL_PHYS_SIZE;
 use crate::consts::PAGE_SIZE;
-use crate::consts::STACK_SIZE;
 use crate::consts::KERNEL_VM_SIZE;
-use crate::consts::USER_STACK_VM_START;
-use crate::consts::USER_STACK_VM_END;
 use crate::kernel::paging::frames;
-use crate::kernel::paging::frames::PhysAddr;
+use crate::kernel::paging::frames::{FrameOwner, PhysAddr};
+
+// Grenzen der Sektionen im Linker-Skript
+extern "C" {
+    static ___KERNEL_DATA_START__: u64;
+    static ___USER_START__: u64;
+    static ___USER_END__: u64;
+    static ___RODATA_END__: u64;
+}
 
 
 // Anzahl Eintraege in einer Seitentabelle
 const PAGE_TABLE_ENTRIES: usize = 512;
 
+// Erster Eintrag in der PML4 fuer den User-Bereich, alle Eintraege davor gehoeren dem Kernel
+const FIRST_USER_PML4_INDEX: usize = KERNEL_VM_SIZE >> 39;
+
+// PML4 mit den Kernel-Tabellen (siehe 'pg_init_kernel_tables'). Die PDPTs darunter
+// werden von allen Adressraeumen gemeinsam genutzt.
+static KERNEL_PML4: AtomicU64 = AtomicU64::new(0);
+
+// EFER.NXE gesetzt (siehe 'boot.asm')? Nur dann darf NO_EXECUTE verwendet werden.
+const EFER_NXE: u64 = 1 << 11;
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// CR4.SMAP gesetzt? Dann muss der Kernel fuer Zugriffe auf User-Seiten 'stac'/'clac'
+// verwenden (siehe 'user_copy'), auf CPUs ohne SMAP sind beide Befehle undefiniert.
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
 // Flags eines Eintrages in der Seitentabelle
 bitflags::bitflags! {
     pub struct PTEflags: u64 {
@@ -41,32 +63,53 @@ bitflags::bitflags! {
         const HUGE_PAGE = 1 << 7;
         const GLOBAL = 1 << 8;
         const FREE = 1 << 9;          // Page-Entry free = 1, used = 0
+        const COW = 1 << 10;          // Copy-on-Write: schreibgeschuetzt, da mit anderen Adressraeumen geteilt
+        const NO_EXECUTE = 1 << 63;   // Seite nicht ausfuehrbar (NX), ohne EFER.NXE reserviert
     }
 }
 
-// Bezügliche der Seitentabelleneinträge lassen wir vorerst alle Einträge im Ring 3 zugreifbar, löschen also nicht das User-Bit.
-// Zudem setzen wir alle Seiten auf schreibbar und sofern mit Page-Frames unterlegt auf „Präsent“.
-// Um andere mögliche Bits in den Seitentabelleneinträgen, wie Caching, No-Execute, Protection Keys etc., kümmern wir uns nicht.
+// Die Seiten des Kernels (1:1 Mapping) sind nur im Ring 0 zugreifbar. Das User-Bit tragen
+// nur die Seiten der User-Programme, die User-Stacks und explizit freigegebene Kernel-Seiten
+// (Sektion '.user_text', siehe 'pg_share_with_user').
+// Ausfuehrbar sind nur Code-Seiten (Kernel-Code und ELF-Segmente mit PF_X), alle anderen
+// Seiten tragen NO_EXECUTE, sofern die CPU es unterstuetzt (siehe 'no_execute').
+// Um andere mögliche Bits in den Seitentabelleneinträgen, wie Caching, Protection Keys etc., kümmern wir uns nicht.
 impl PTEflags {
+    // Eintraege in PML4, PDPT und PD (Kernel und User). Die CPU verknuepft die Rechte aller
+    // Ebenen, daher entscheidet allein der Eintrag in der PT, ob Ring 3 zugreifen darf.
+    fn flags_for_tables() -> Self {
+        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::USER
+    }
+
+    // Kernel-Daten, Heap und restlicher Speicher: schreibbar, nicht ausfuehrbar
     fn flags_for_kernel_pages() -> Self {
-        /*
-        *   Bezügliche der Seitentabelleneinträge lassen wir vorerst alle Einträge im Ring 3 zugreifbar, löschen
-        *   also nicht das User-Bit. Das ist noch notwendig, damit wir den Code im Ring 3 ausführen können,
-        *   wird aber in einem späteren Übungsblatt abgeschafft.
-        */
-        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::GLOBAL | PTEflags::USER
+        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::GLOBAL | PTEflags::no_execute()
     }
 
-    fn flags_for_kernel_int_pages_user_present() -> Self {
-        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::GLOBAL | PTEflags::USER
+    // User-Seiten sind je Adressraum verschieden und duerfen daher nicht GLOBAL sein,
+    // sonst ueberleben sie im TLB das Umschalten von CR3. Code-Seiten loeschen NO_EXECUTE.
+    fn flags_for_user_pages() -> Self {
+        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::USER | PTEflags::no_execute()
     }
 
-    fn flags_for_kernel_page_zero() -> Self {
-        PTEflags::WRITEABLE | PTEflags::GLOBAL | PTEflags::USER
+    // Rechte einer User-Seite, 'user' = false -> im Ring 3 nicht zugreifbar (PROT_NONE)
+    fn for_user(user: bool, writeable: bool, executable: bool) -> Self {
+        let mut flags = PTEflags::flags_for_user_pages();
+        flags.set(PTEflags::USER, user);
+        flags.set(PTEflags::WRITEABLE, writeable);
+        if executable {
+            flags.remove(PTEflags::NO_EXECUTE);
+        }
+        flags
     }
 
-    fn flags_for_user_pages() -> Self {
-        PTEflags::PRESENT | PTEflags::WRITEABLE | PTEflags::GLOBAL | PTEflags::USER
+    // NO_EXECUTE, falls EFER.NXE gesetzt ist, sonst leer
+    fn no_execute() -> Self {
+        if NX_ENABLED.load(Ordering::Relaxed) {
+            PTEflags::NO_EXECUTE
+        } else {
+            PTEflags::empty()
+        }
     }
 }
 
@@ -151,6 +194,62 @@ impl core::fmt::Debug for PageTableEntry {
 }
 
 
+// Seitengroessen, grosse Seiten werden mit HUGE_PAGE im PD (2 MB) bzw. PDPT (1 GB) eingetragen
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    // Groesse in Bytes
+    pub fn bytes(&self) -> usize {
+        PAGE_SIZE << (9 * (self.level() - 1))
+    }
+
+    // Anzahl der 4 KB Page-Frames
+    pub fn frames(&self) -> usize {
+        self.bytes() / PAGE_SIZE
+    }
+
+    // Ebene der Tabelle, in der die Seite eingetragen wird (1 = PT, 2 = PD, 3 = PDPT)
+    fn level(&self) -> usize {
+        match self {
+            PageSize::Size4K => 1,
+            PageSize::Size2M => 2,
+            PageSize::Size1G => 3,
+        }
+    }
+
+    fn from_level(level: usize) -> Self {
+        match level {
+            1 => PageSize::Size4K,
+            2 => PageSize::Size2M,
+            3 => PageSize::Size1G,
+            _ => panic!("PageSize: keine Seite auf Ebene {}", level),
+        }
+    }
+}
+
+// Unterstuetzt die CPU 1 GB Seiten? (CPUID 0x8000_0001, EDX Bit 26)
+pub fn has_1g_pages() -> bool {
+    let max_ext = unsafe { core::arch::x86_64::__cpuid(0x8000_0000) }.eax;
+    if max_ext < 0x8000_0001 {
+        return false;
+    }
+    let edx = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) }.edx;
+    edx & (1 << 26) != 0
+}
+
+// Fehler beim Einrichten eines Mappings
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MapError {
+    AlreadyMapped, // Seite ist bereits present
+    HugePage,      // Adresse liegt in einer grossen Seite (2 MB / 1 GB)
+    NotAligned,    // virtuelle oder physikalische Adresse nicht auf die Seitengroesse aligniert
+}
+
 // Funktionen fuer die Page-Tables
 #[repr(transparent)]
 pub struct PageTable {
@@ -172,446 +271,222 @@ impl PageTable {
         }
     }
 
-    // Diese Funktion richtet ein neues Mapping ein
-    // 'vm_addr':     virtuelle Startaddresse des Mappings
-    // 'nr_of_pages': Anzahl der Seiten, die ab 'vm_addr' gemappt werden sollen
-    fn mmap_kernel(&mut self, vm_addr: usize, nr_of_pages: usize) { // war mal "mut vm_addr: usize" aber compiler hat geraten dass unmute
-    // bekommen eine 4kb seite der pml4
-    // hier muss jetzt an der entsrechenden stelle, die adresse der pdpte eingetragen werden
-        Self::create_pdpt_in_pml4_kernel(self, vm_addr, nr_of_pages);
-    }
-
-    // Diese Funktion richtet ein neues Mapping ein
-    // 'vm_addr':     virtuelle Startaddresse des Mappings
-    // 'nr_of_pages': Anzahl der Seiten, die ab 'vm_addr' gemappt werden sollen
-    fn mmap_user(&mut self, vm_addr: usize, nr_of_pages: usize) { // war mal "mut vm_addr: usize" aber compiler hat geraten dass unmute
-    // bekommen eine 4kb seite der pml4
-    // hier muss jetzt an der entsrechenden stelle, die adresse der pdpte eingetragen werden
-        Self::create_pdpt_in_pml4_user(self, vm_addr, nr_of_pages);
-    }
-
-// ####################################################################################################################################
-// ##################################################### PML4 mit PDPT verdrahten #####################################################
-// ####################################################################################################################################
-    fn create_pdpt_in_pml4_kernel(pml4: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) {
-        kprintln!("create_pdpt_in_pml4_kernel: level=4, {} Seiten ab 0x{:x} zu mappen.",nr_of_pages,start_vm_addr);
-    
-        // ---------------------------------------------------------
-        // 1. index bestimmen PML4 => Bits 47..39 (also >> 39 & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pml4 = ((start_vm_addr >> 39) & 0x1ff) as usize;
-    
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als PDPT initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für PDPT anfordern
-        let pd_frame = frames::pf_alloc(1, true);
-        assert!(pd_frame != PhysAddr(0),"pf_alloc() für PDPT schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pdpt_virt_ptr = pd_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
-        // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pdpt_table = unsafe { &mut *pdpt_virt_ptr };
-        for entry in pdpt_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pdpt in pml4 eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pml4 der auf neu erstelle pdpt tabelle refferenziert
-        pml4.entries[index_pml4] = PageTableEntry::new(pd_frame, PTEflags::flags_for_kernel_pages());
-    
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::create_pd_in_pdpt_kernel(pdpt_table, start_vm_addr, nr_of_pages);
-    
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pml4 == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                assert!(index_pml4 != 511,"create_pdpt_in_pml4_kernel() limit für vierstufiges paging erreicht! Alle 512 Einträge in PML4 aufgebraucht!");
-            } else {
-                return Self::create_pdpt_in_pml4_kernel(pml4, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
-            }
-        }
+    // Index von 'vm_addr' in einer Tabelle der Ebene 'level' (4 = PML4, ..., 1 = PT)
+    fn index(vm_addr: usize, level: usize) -> usize {
+        (vm_addr >> (12 + 9 * (level - 1))) & 0x1ff
     }
-    
-// ####################################################################################################################################
-// ###################################################### PDPT mit PD verdrahten ######################################################
-// ####################################################################################################################################
-    fn create_pd_in_pdpt_kernel(pdpt: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("create_pd_in_pdpt_kernel: level=3, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
-        // ---------------------------------------------------------
-        // 1. index bestimmen pdpt => Bits 38...30 (also >> 3o & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pdpt = ((start_vm_addr >> 30) & 0x1ff) as usize;
-    
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als pd initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für pd anfordern
-        let pd_frame = frames::pf_alloc(1, true);
-        assert!(pd_frame != PhysAddr(0),"pf_alloc() für pd schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pd_virt_ptr = pd_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
-        // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pd_table = unsafe { &mut *pd_virt_ptr };
-        for entry in pd_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pd in pdpt eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pdpt der auf neu erstelle pd tabelle refferenziert
-        pdpt.entries[index_pdpt] = PageTableEntry::new(pd_frame, PTEflags::flags_for_kernel_pages());
-    
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::create_pt_in_pd_kernel(pd_table, start_vm_addr, nr_of_pages);
-    
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pdpt == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                return unmapped;
-            } else {
-                return Self::create_pd_in_pdpt_kernel(pdpt, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
+
+    // Tabelle hinter 'entry' zurueckgeben. Ist der Eintrag noch nicht present, wird
+    // eine neue, genullte Tabelle alloziert und eingetragen. Vorhandene Tabellen werden
+    // nie ueberschrieben, mehrere Mappings (z.B. Stacks der Threads eines Prozesses)
+    // koennen sich Tabellen teilen.
+    fn get_or_create_table(entry: &mut PageTableEntry) -> Result<&'static mut PageTable, MapError> {
+        if entry.is_present() {
+            if entry.get_flags().contains(PTEflags::HUGE_PAGE) {
+                return Err(MapError::HugePage);
             }
+            return Ok(unsafe { &mut *entry.get_addr().as_mut_ptr::<PageTable>() });
         }
-        return 0
-    }
-
-// ####################################################################################################################################
-// ####################################################### PD mit PT verdrahten #######################################################
-// ####################################################################################################################################
-    fn create_pt_in_pd_kernel(pd: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("create_pt_in_pd_kernel: level=2, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
-        // ---------------------------------------------------------
-        // 1. index bestimmen pd => Bits 29..21 (also >> 21 & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pd = ((start_vm_addr >> 21) & 0x1ff) as usize;
-    
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als pt initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für pt anfordern
-        let pt_frame = frames::pf_alloc(1, true);
-        assert!(pt_frame != PhysAddr(0),"pf_alloc() für pt schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pt_virt_ptr = pt_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
+
+        // Physikalische Seite für die Tabelle anfordern
+        let frame = frames::pf_alloc(1, true, FrameOwner::PageTable);
+        assert!(frame != PhysAddr(0),"pf_alloc() für Tabelle schlug fehl oder lieferte 0!");
+
         // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pt_table = unsafe { &mut *pt_virt_ptr };
-        for entry in pt_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pdpt in pml4 eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pml4 der auf neu erstelle pdpt tabelle refferenziert
-        pd.entries[index_pd] = PageTableEntry::new(pt_frame, PTEflags::flags_for_kernel_pages());
-    
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::map_pages_in_pt_kernel(pt_table, start_vm_addr, nr_of_pages);
-    
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen oder zurück geben
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pd == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                return unmapped;
-            } else {
-                return Self::create_pt_in_pd_kernel(pd, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
-            }
+        let table = unsafe { &mut *frame.as_mut_ptr::<PageTable>() };
+        for e in table.entries.iter_mut() {
+            *e = PageTableEntry(0);
         }
-        return 0
-    }
-
-// ####################################################################################################################################
-// ##################################################### PT mit Seiten verdrahten #####################################################
-// ####################################################################################################################################
-    fn map_pages_in_pt_kernel(pt: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("map_pages_in_pt_kernel: level=1, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
-
-        // index in der PT (unterste Ebene) ermitteln Bits [20:12] der virtuellen Adresse → (vm_addr >> 12) & 0x1ff
-        let pt_index = ((start_vm_addr >> 12) & 0x1ff) as usize;
-    
-        // wieviele einträge haben wir ab pt_index noch frei, bis die Tabellenende (512 Einträge) 
-        let max_entries = PAGE_TABLE_ENTRIES - pt_index;
-        let pages_to_map = core::cmp::min(nr_of_pages, max_entries);
-        let flags_kernel_present_accessuser = PTEflags::flags_for_kernel_int_pages_user_present(); 
-
-        let first_address = start_vm_addr + 0 * PAGE_SIZE;
-        let last_address = start_vm_addr + pages_to_map * PAGE_SIZE;
-        for i in 0..pages_to_map {
-            let vm_addr  = start_vm_addr + i * PAGE_SIZE;
-            let phys_addr = vm_addr;    // Identity: 1:1
-    
-            // In der PT eintragen
-            pt.entries[pt_index + i].set_addr(PhysAddr::new(phys_addr as u64));
-            if phys_addr == 0
-            {
-                pt.entries[pt_index + i].set_flags(PTEflags::flags_for_kernel_page_zero());
-                kprintln!("###### map_pages_in_pt_kernel: special case address 0 auf nicht present");
-            } else {
-                pt.entries[pt_index + i].set_flags(flags_kernel_present_accessuser);
+
+        // neuen Eintrag der auf die neu erstellte tabelle refferenziert
+        *entry = PageTableEntry::new(frame, PTEflags::flags_for_tables());
+        Ok(table)
+    }
+
+    // Vorhandene Seite (4 KB oder gross) fuer 'vm_addr' suchen, ohne Tabellen anzulegen
+    fn find_leaf(&mut self, vm_addr: usize) -> Option<(&'static mut PageTableEntry, PageSize)> {
+        let mut table: *mut PageTable = self;
+        for level in (1..=4).rev() {
+            let entry = unsafe { &mut (*table).entries[Self::index(vm_addr, level)] };
+            if !entry.is_present() {
+                return None;
             }
-        }
-    
-        // anzahl nicht-gemappter Seiten zurückgeben, falls nr_of_pages > pages_to_map nicht alle seiten gemappt
-        kprintln!(
-            "map_pages_in_pt_kernel: {} viele gemappte ab adress 0x{:x} bis Letzte 0x{:x}. (todo: {} pages)", 
-                pages_to_map, 
-                first_address, 
-                last_address,
-                nr_of_pages-pages_to_map
-        );
-
-        return nr_of_pages - pages_to_map;
-    }	
-
-    //############################################################################################################################################
-    //################################################################### User ###################################################################
-    //############################################################################################################################################
-
-    // ####################################################################################################################################
-    // ##################################################### PML4 mit PDPT verdrahten #####################################################
-    // ####################################################################################################################################
-    fn create_pdpt_in_pml4_user(pml4: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) {
-        kprintln!("create_pdpt_in_pml4_user: level=4, {} Seiten ab 0x{:x} zu mappen.",nr_of_pages,start_vm_addr);
-
-        // ---------------------------------------------------------
-        // 1. index bestimmen PML4 => Bits 47..39 (also >> 39 & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pml4 = ((start_vm_addr >> 39) & 0x1ff) as usize;
-
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als PDPT initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für PDPT anfordern
-        let pd_frame = frames::pf_alloc(1, true);
-        assert!(pd_frame != PhysAddr(0),"pf_alloc() für PDPT schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pdpt_virt_ptr = pd_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
-        // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pdpt_table = unsafe { &mut *pdpt_virt_ptr };
-        for entry in pdpt_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pdpt in pml4 eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pml4 der auf neu erstelle pdpt tabelle refferenziert
-        pml4.entries[index_pml4] = PageTableEntry::new(pd_frame, PTEflags::flags_for_user_pages());
-
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::create_pd_in_pdpt_user(pdpt_table, start_vm_addr, nr_of_pages);
-
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pml4 == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                assert!(index_pml4 != 511,"create_pdpt_in_pml4_user() limit für vierstufiges paging erreicht! Alle 512 Einträge in PML4 aufgebraucht!");
-            } else {
-                return Self::create_pdpt_in_pml4_user(pml4, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
+            if level == 1 || entry.get_flags().contains(PTEflags::HUGE_PAGE) {
+                return Some((entry, PageSize::from_level(level)));
             }
+            table = entry.get_addr().as_mut_ptr::<PageTable>();
         }
+        None
     }
 
-    // ####################################################################################################################################
-    // ###################################################### PDPT mit PD verdrahten ######################################################
-    // ####################################################################################################################################
-    fn create_pd_in_pdpt_user(pdpt: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("create_pd_in_pdpt_user: level=3, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
-        // ---------------------------------------------------------
-        // 1. index bestimmen pdpt => Bits 38...30 (also >> 3o & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pdpt = ((start_vm_addr >> 30) & 0x1ff) as usize;
-
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als pd initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für pd anfordern
-        let pd_frame = frames::pf_alloc(1, true);
-        assert!(pd_frame != PhysAddr(0),"pf_alloc() für pd schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pd_virt_ptr = pd_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
-        // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pd_table = unsafe { &mut *pd_virt_ptr };
-        for entry in pd_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pd in pdpt eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pdpt der auf neu erstelle pd tabelle refferenziert
-        pdpt.entries[index_pdpt] = PageTableEntry::new(pd_frame, PTEflags::flags_for_user_pages());
-
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::create_pt_in_pd_user(pd_table, start_vm_addr, nr_of_pages);
-
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pdpt == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                return unmapped;
-            } else {
-                return Self::create_pd_in_pdpt_user(pdpt, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
-            }
+    // Eintrag fuer eine Seite der Groesse 'size' an 'vm_addr' suchen, fehlende Tabellen anlegen
+    fn get_or_create_entry(&mut self, vm_addr: usize, size: PageSize) -> Result<&'static mut PageTableEntry, MapError> {
+        let mut table: &mut PageTable = unsafe { &mut *(self as *mut PageTable) };
+        for level in (size.level() + 1..=4).rev() {
+            table = Self::get_or_create_table(&mut table.entries[Self::index(vm_addr, level)])?;
         }
-        return 0
-    }
-
-    // ####################################################################################################################################
-    // ####################################################### PD mit PT verdrahten #######################################################
-    // ####################################################################################################################################
-    fn create_pt_in_pd_user(pd: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("create_pt_in_pd_user: level=2, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
-        // ---------------------------------------------------------
-        // 1. index bestimmen pd => Bits 29..21 (also >> 21 & 0x1FF)
-        // ---------------------------------------------------------
-        let index_pd = ((start_vm_addr >> 21) & 0x1ff) as usize;
-
-        // ---------------------------------------------------------
-        // 2. neue tabelle allozieren und als pt initialisieren
-        // ---------------------------------------------------------
-        // Physikalische Seite für pt anfordern
-        let pt_frame = frames::pf_alloc(1, true);
-        assert!(pt_frame != PhysAddr(0),"pf_alloc() für pt schlug fehl oder lieferte 0!");
-        // page table pointer auf adresse erstellen
-        let pt_virt_ptr = pt_frame.as_mut_ptr::<PageTable>();
-
-        // ---------------------------------------------------------
-        // 3. tabelle nullen
-        // ---------------------------------------------------------
-        // Tabelle als 0 gefüllte tabelle initialisieren an erhaltener adresse
-        let pt_table = unsafe { &mut *pt_virt_ptr };
-        for entry in pt_table.entries.iter_mut() {
-            *entry = PageTableEntry(0);
-        }
-
-        // ---------------------------------------------------------
-        // 4. pdpt in pml4 eintragen
-        // ---------------------------------------------------------
-        // neune Eintrag in pml4 der auf neu erstelle pdpt tabelle refferenziert
-        pd.entries[index_pd] = PageTableEntry::new(pt_frame, PTEflags::flags_for_user_pages());
-
-        // ---------------------------------------------------------
-        // 5. nächste Funktion delegieren
-        // ---------------------------------------------------------
-        let unmapped = Self::map_pages_in_pt_user(pt_table, start_vm_addr, nr_of_pages);
-
-        // ---------------------------------------------------------
-        // 6. falls überlauf, rekusiv erneut aufrufen oder zurück geben
-        // ---------------------------------------------------------
-        if unmapped > 0 {
-            if index_pd == 511 {
-                // Kein weiterer eintrag in diesem PD verfügbar -> muss neuen pd anlegen
-                return unmapped;
+        Ok(&mut table.entries[Self::index(vm_addr, size.level())])
+    }
+
+    // Hilfsfunktion von 'AddressSpace::walk': besucht alle Eintraege dieser Tabelle
+    // der Ebene 'level', die [vm_start, vm_end) schneiden. 'base' ist die virtuelle
+    // Adresse des ersten Eintrags.
+    fn walk_level<F>(&mut self, level: usize, base: usize, vm_start: usize, vm_end: usize, f: &mut F)
+    where
+        F: FnMut(usize, &mut PageTableEntry, PageSize),
+    {
+        let entry_size = PAGE_SIZE << (9 * (level - 1));
+
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            let vm_addr = base + index * entry_size;
+            if vm_addr >= vm_end || vm_addr + entry_size <= vm_start {
+                continue;
+            }
+            if !entry.is_present() {
+                continue;
+            }
+            if level == 1 || entry.get_flags().contains(PTEflags::HUGE_PAGE) {
+                f(vm_addr, entry, PageSize::from_level(level));
             } else {
-                return Self::create_pt_in_pd_user(pd, start_vm_addr + (nr_of_pages - unmapped) * PAGE_SIZE, unmapped);
+                let table = unsafe { &mut *entry.get_addr().as_mut_ptr::<PageTable>() };
+                table.walk_level(level - 1, vm_addr, vm_start, vm_end, f);
             }
         }
-        return 0
     }
+}
 
-    // ####################################################################################################################################
-    // ##################################################### PT mit Seiten verdrahten #####################################################
-    // ####################################################################################################################################
 
-    /*
-    * Hier muss Code eingefuegt werden
-    *  Soll liegen für jeden Prozess an:
-    *  virtuellen Adressbereich liegen (64 TiB bis 64 TiB + 64 KiB) 
-    * 
-    * use crate::consts::USER_STACK_VM_START;
-    * use crate::consts::USER_STACK_VM_END;
-    */
+// Ein Adressraum, beschrieben durch seine PML4. Alle Operationen legen fehlende
+// Tabellen bei Bedarf an und nutzen vorhandene weiter. Page-Frames hinter den
+// Eintraegen werden hier weder alloziert noch freigegeben, das ist Sache des Aufrufers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AddressSpace {
+    pml4_addr: PhysAddr,
+}
 
-    fn map_pages_in_pt_user(pt: &mut PageTable, start_vm_addr: usize, nr_of_pages: usize) -> usize {
-        kprintln!("map_pages_in_pt_user: level=1, {} viele seiten ab 0x{:x} zu mappen.", nr_of_pages, start_vm_addr);
+impl AddressSpace {
+    pub fn new(pml4_addr: PhysAddr) -> Self {
+        assert!(pml4_addr != PhysAddr(0));
+        AddressSpace { pml4_addr }
+    }
 
-        // index in der PT (unterste Ebene) ermitteln Bits [20:12] der virtuellen Adresse → (vm_addr >> 12) & 0x1ff
-        let pt_index = ((start_vm_addr >> 12) & 0x1ff) as usize;
+    pub fn get_pml4_addr(&self) -> PhysAddr {
+        self.pml4_addr
+    }
 
-        // wieviele einträge haben wir ab pt_index noch frei, bis die Tabellenende (512 Einträge) 
-        let max_entries = PAGE_TABLE_ENTRIES - pt_index;
-        let pages_to_map = core::cmp::min(nr_of_pages, max_entries);
-        let user_flags = PTEflags::flags_for_user_pages(); 
+    // Ist dieser Adressraum gerade in CR3 eingetragen?
+    pub fn is_active(&self) -> bool {
+        self.pml4_addr == PageTable::get_cr3()
+    }
 
-        // physikalischen Page-Frame (4KiB) anfordern
-        let phys_addr = frames::pf_alloc(pages_to_map, false);
-        assert!(phys_addr != PhysAddr(0), "Fehler: Keine freien Frames mehr!");
+    fn pml4(&self) -> &'static mut PageTable {
+        unsafe { &mut *(self.pml4_addr.as_mut_ptr::<PageTable>()) }
+    }
+
+    // TLB-Eintrag fuer 'vm_addr' verwerfen, falls der Adressraum aktiv ist
+    fn flush(&self, vm_addr: usize) {
+        if self.is_active() {
+            unsafe { x86::tlb::flush(vm_addr) };
+        }
+    }
+
+    // 4 KB Seite 'vm_addr' auf den Page-Frame 'phys_addr' abbilden, siehe 'map_page'
+    pub fn map(&self, vm_addr: usize, phys_addr: PhysAddr, flags: PTEflags) -> Result<(), MapError> {
+        self.map_page(vm_addr, phys_addr, PageSize::Size4K, flags)
+    }
 
-        let first_address = start_vm_addr + 0 * PAGE_SIZE;
-        let last_address = start_vm_addr + pages_to_map * PAGE_SIZE;
-        for i in 0..pages_to_map {
-            let idx = pt_index + i;
+    /**
+        Description: Map the page at `vm_addr` to the page frames starting at `phys_addr`.
 
-            // PT aktualisieren
-            pt.entries[idx].set_addr(phys_addr);
-            pt.entries[pt_index + i].set_flags(user_flags);
+        Parameters: \
+               `vm_addr`   virtual address (aligned to `size`) \
+               `phys_addr` physical address (aligned to `size`) \
+               `size`      page size, HUGE_PAGE is set for 2 MB and 1 GB pages \
+               `flags`     flags of the entry, PRESENT is always set
 
+        Return: \
+               `Err` if the page is already mapped, the entry is not changed
+    */
+    pub fn map_page(&self, vm_addr: usize, phys_addr: PhysAddr, size: PageSize, flags: PTEflags) -> Result<(), MapError> {
+        if vm_addr % size.bytes() != 0 || phys_addr.raw() % size.bytes() as u64 != 0 {
+            return Err(MapError::NotAligned);
+        }
+        let entry = self.pml4().get_or_create_entry(vm_addr, size)?;
+        if entry.is_present() {
+            return Err(MapError::AlreadyMapped);
         }
+        let mut flags = flags;
+        flags.set(PTEflags::HUGE_PAGE, size != PageSize::Size4K);
+        *entry = PageTableEntry::new(phys_addr, flags);
+        Ok(())
+    }
 
-        // anzahl nicht-gemappter Seiten zurückgeben, falls nr_of_pages > pages_to_map nicht alle seiten gemappt
-        kprintln!(
-            "map_pages_in_pt_user: {} viele gemappte ab adress 0x{:x} bis Letzte 0x{:x}. (todo: {} pages)", 
-                pages_to_map, 
-                first_address, 
-                last_address,
-                nr_of_pages-pages_to_map
-        );
+    // 'nr_of_pages' Seiten ab 'vm_start' auf die zusammenhaengenden Page-Frames ab
+    // 'phys_start' abbilden. Bricht beim ersten Fehler ab, bereits gemappte Seiten bleiben.
+    pub fn map_range(&self, vm_start: usize, phys_start: PhysAddr, nr_of_pages: usize, flags: PTEflags) -> Result<(), MapError> {
+        for i in 0..nr_of_pages {
+            let offset = i * PAGE_SIZE;
+            self.map(vm_start + offset, PhysAddr::new(phys_start.raw() + offset as u64), flags)?;
+        }
+        Ok(())
+    }
 
-        return nr_of_pages - pages_to_map;
+    // Mapping der Seite entfernen, die 'vm_addr' enthaelt (bei grossen Seiten die ganze Seite)
+    // Rueckgabe: erster Page-Frame und Groesse der Seite (nicht freigegeben), None falls nicht gemappt
+    pub fn unmap(&self, vm_addr: usize) -> Option<(PhysAddr, PageSize)> {
+        let (entry, size) = self.pml4().find_leaf(vm_addr)?;
+        let frame = entry.get_addr();
+        *entry = PageTableEntry(0);
+        self.flush(vm_addr & !(size.bytes() - 1));
+        Some((frame, size))
+    }
+
+    // Physikalische Adresse zu 'vm_addr' ermitteln
+    // Rueckgabe: None, falls die Seite nicht gemappt ist
+    pub fn translate(&self, vm_addr: usize) -> Option<PhysAddr> {
+        let (entry, size) = self.pml4().find_leaf(vm_addr)?;
+        Some(PhysAddr::new(entry.get_addr().raw() + (vm_addr % size.bytes()) as u64))
+    }
+
+    // Flags der gemappten Seite ersetzen, die 'vm_addr' enthaelt (PRESENT und
+    // bei grossen Seiten HUGE_PAGE bleiben gesetzt)
+    // Rueckgabe: false, falls die Seite nicht gemappt ist
+    pub fn protect(&self, vm_addr: usize, flags: PTEflags) -> bool {
+        match self.pml4().find_leaf(vm_addr) {
+            Some((entry, size)) => {
+                let mut flags = flags | PTEflags::PRESENT;
+                flags.set(PTEflags::HUGE_PAGE, size != PageSize::Size4K);
+                entry.set_flags(flags);
+                self.flush(vm_addr & !(size.bytes() - 1));
+                true
+            }
+            None => false,
+        }
     }
 
+    // Ruft 'f' fuer jeden vorhandenen Eintrag der untersten Ebene (bzw. jede grosse
+    // Seite) im Bereich [vm_start, vm_end) auf, mit virtueller Adresse und Groesse der Seite.
+    // Nicht vorhandene Tabellen werden uebersprungen, es wird nichts angelegt.
+    // Aendert 'f' einen Eintrag, muss der Aufrufer den TLB selbst aktualisieren.
+    pub fn walk<F>(&self, vm_start: usize, vm_end: usize, mut f: F)
+    where
+        F: FnMut(usize, &mut PageTableEntry, PageSize),
+    {
+        self.pml4().walk_level(4, 0, vm_start, vm_end, &mut f);
+    }
 }
 
 
-// Fuer die Page-Tables werden bei Bedarf Page-Frames alloziert
+// Legt die Tabellen fuer den Kernel an, die sich alle Adressraeume teilen (einmalig
+// beim Booten). Fuer die Page-Tables werden bei Bedarf Page-Frames alloziert.
 pub fn pg_init_kernel_tables() -> PhysAddr {
     kprintln!("pg_init_kernel_tables");
+    assert!(KERNEL_PML4.load(Ordering::SeqCst) == 0, "pg_init_kernel_tables: bereits initialisiert!");
+
+    // NXE wird in 'boot.asm' gesetzt, sofern die CPU das NX-Bit kennt
+    let efer = unsafe { x86::msr::rdmsr(x86::msr::IA32_EFER) };
+    NX_ENABLED.store(efer & EFER_NXE != 0, Ordering::SeqCst);
+    kprintln!("   NX-Bit: {}", if efer & EFER_NXE != 0 { "aktiv" } else { "nicht unterstuetzt" });
 
     // Ausrechnen wie viel Seiten "gemappt" werden muessen
     let max_phys_addr: usize = PhysAddr::get_max_phys_addr().raw() as usize;
@@ -621,41 +496,403 @@ pub fn pg_init_kernel_tables() -> PhysAddr {
     kprintln!("   max_phys_addr = 0x{:x}", max_phys_addr);
 
     // Alloziere eine Tabelle fuer Page Map Level 4 (PML4) -> 4 KB
-    let pml4_addr = frames::pf_alloc(1, true);
+    let pml4_addr = frames::pf_alloc(1, true, FrameOwner::PageTable);
     assert!(pml4_addr != PhysAddr(0));
     kprintln!("pml4_addr = {:?}", pml4_addr);
 
-    // Type-Cast der pml4-Tabllenadresse auf "PageTable"
-    let pml4_table;
-    unsafe { pml4_table = &mut *(pml4_addr.as_mut_ptr::<PageTable>()) }
+    // Kernel 1:1 mappen
+    let space = AddressSpace::new(pml4_addr);
+    map_kernel_identity(&space, nr_of_pages * PAGE_SIZE);
+
+    // Code und Daten fuer den Ring 3 im Kernel-Image freigeben
+    let (user_start, user_end) = get_user_section();
+    pg_share_with_user(pml4_addr, user_start, (user_end - user_start) / PAGE_SIZE);
 
-    pml4_table.mmap_kernel(0, nr_of_pages);
+    // Alle Kernel-Eintraege der PML4 sofort belegen, damit spaetere Aenderungen im
+    // Kernel-Bereich in allen Adressraeumen sichtbar sind (es werden nur PDPTs geteilt)
+    let pml4_table = space.pml4();
+    for entry in pml4_table.entries[..FIRST_USER_PML4_INDEX].iter_mut() {
+        PageTable::get_or_create_table(entry).expect("pg_init_kernel_tables: Eintrag in der PML4 defekt!");
+    }
+
+    KERNEL_PML4.store(pml4_addr.raw(), Ordering::SeqCst);
     kprintln!("pg_init_kernel_tables: returning pml4_addr = 0x{:x}, init done", pml4_addr.raw());   
     return pml4_addr;
 }
 
-// Diese Funktion richtet ein Mapping fuer den User-Mode Stack ein
-pub fn pg_mmap_user_stack(pml4_addr: PhysAddr) -> *mut u8 {
+// Legt einen neuen Adressraum an: eine eigene PML4, deren Kernel-Eintraege auf die
+// gemeinsamen Tabellen aus 'pg_init_kernel_tables' zeigen. Der User-Bereich ist leer.
+pub fn pg_create_address_space() -> PhysAddr {
+    let kernel_pml4 = KERNEL_PML4.load(Ordering::SeqCst);
+    assert!(kernel_pml4 != 0, "pg_create_address_space: Kernel-Tabellen fehlen!");
+    let kernel_table = unsafe { &*(kernel_pml4 as *const PageTable) };
 
-    /*
-    *  Soll liegen für jeden Prozess an:
-    *  virtuellen Adressbereich liegen (64 TiB bis 64 TiB + 64 KiB) 
-    */
+    let pml4_addr = frames::pf_alloc(1, true, FrameOwner::PageTable);
+    assert!(pml4_addr != PhysAddr(0), "pg_create_address_space: keine PML4!");
 
-    assert!(pml4_addr != PhysAddr(0));
-    kprintln!("pml4_addr = {:?}", pml4_addr);
+    let pml4_table = unsafe { &mut *(pml4_addr.as_mut_ptr::<PageTable>()) };
+    pml4_table.entries[..FIRST_USER_PML4_INDEX].copy_from_slice(&kernel_table.entries[..FIRST_USER_PML4_INDEX]);
+    pml4_addr
+}
 
-    // Type-Cast der pml4-Tabllenadresse auf "PageTable"
-    let pml4_table;
-    unsafe { pml4_table = &mut *(pml4_addr.as_mut_ptr::<PageTable>()) }
+// Schreibschutz auch im Ring 0 einschalten (CR0.WP), sonst wuerde der Kernel
+// beim Schreiben in User-Puffer Copy-on-Write-Seiten unbemerkt veraendern
+pub fn pg_enable_write_protect() {
+    unsafe {
+        let cr0 = x86::controlregs::cr0();
+        x86::controlregs::cr0_write(cr0 | x86::controlregs::Cr0::CR0_WRITE_PROTECT);
+    }
+}
 
-    // anzahl der benötigten seiten berechnen und anfordern
-    let nr_of_pages = (STACK_SIZE) / PAGE_SIZE;
-    pml4_table.mmap_user(USER_STACK_VM_START, nr_of_pages);
+// Globale Seiten einschalten (CR4.PGE). Die Kernel-Seiten tragen GLOBAL und bleiben
+// so beim Umschalten des Adressraums in '_thread_switch' im TLB.
+pub fn pg_enable_global_pages() {
+    unsafe {
+        let cr4 = x86::controlregs::cr4();
+        x86::controlregs::cr4_write(cr4 | x86::controlregs::Cr4::CR4_ENABLE_GLOBAL_PAGES);
+    }
+}
+
+// SMEP (Ring 0 fuehrt keinen Code aus User-Seiten aus) und SMAP (Ring 0 greift nur
+// mit gesetztem AC-Flag auf User-Seiten zu) einschalten, sofern die CPU sie kennt.
+// CPUID 7, EBX Bit 7 bzw. 20
+pub fn pg_enable_smep_smap() {
+    let max_leaf = unsafe { core::arch::x86_64::__cpuid(0) }.eax;
+    let ebx = if max_leaf >= 7 {
+        unsafe { core::arch::x86_64::__cpuid_count(7, 0) }.ebx
+    } else {
+        0
+    };
+    let smep = ebx & (1 << 7) != 0;
+    let smap = ebx & (1 << 20) != 0;
+
+    unsafe {
+        let mut cr4 = x86::controlregs::cr4();
+        if smep {
+            cr4 |= x86::controlregs::Cr4::CR4_ENABLE_SMEP;
+        }
+        if smap {
+            cr4 |= x86::controlregs::Cr4::CR4_ENABLE_SMAP;
+        }
+        x86::controlregs::cr4_write(cr4);
+    }
+    SMAP_ENABLED.store(smap, Ordering::SeqCst);
+    kprintln!("pg_enable_smep_smap: SMEP {}, SMAP {}", smep, smap);
+}
+
+// Ist SMAP aktiv? (siehe 'pg_enable_smep_smap')
+pub fn pg_smap_enabled() -> bool {
+    SMAP_ENABLED.load(Ordering::Relaxed)
+}
+
+// Liegt [vm_addr, vm_addr + len) in der Sektion '.user_text', die alle
+// Adressraeume gemeinsam haben und im Ring 3 lesen duerfen?
+pub fn pg_is_user_section(vm_addr: usize, len: usize) -> bool {
+    let (user_start, user_end) = get_user_section();
+    match vm_addr.checked_add(len) {
+        Some(end) => vm_addr >= user_start && end <= user_end,
+        None => false,
+    }
+}
 
-    // start adress des stack zurück geben
-    let user_stack_vm_start_ptr = USER_STACK_VM_START as *mut u8;
-    user_stack_vm_start_ptr
+// Bildet [0, 'end') 1:1 ab, jeweils mit der groessten passenden Seite (1 GB sofern
+// die CPU das kann, sonst 2 MB). 4 KB Seiten nur an den Raendern und dort, wo
+// einzelne Seiten andere Rechte brauchen: Seite 0 bleibt nicht present (Null-Pointer),
+// die Sektion '.user_text' wird seitenweise fuer den Ring 3 freigegeben und an den
+// Grenzen von Code, Konstanten und Daten wechseln die Rechte (siehe 'kernel_flags').
+fn map_kernel_identity(space: &AddressSpace, end: usize) {
+    let (user_start, user_end) = get_user_section();
+    let (code_start, code_end, rodata_end) = get_kernel_sections();
+    let use_1g = has_1g_pages();
+    let sizes = [PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+    let borders = [code_start, code_end, rodata_end];
+
+    // Muss [start, start + len) mit 4 KB Seiten gemappt werden?
+    let needs_4k = |start: usize, len: usize| {
+        start < PAGE_SIZE
+            || (start < user_end && start + len > user_start)
+            || borders.iter().any(|b| start < *b && *b < start + len)
+    };
+
+    // Code ist nur les- und ausfuehrbar, Konstanten nur lesbar, alles andere schreibbar (NX)
+    let kernel_flags = |vm_addr: usize| {
+        let flags = PTEflags::flags_for_kernel_pages();
+        if vm_addr >= code_start && vm_addr < code_end {
+            flags - PTEflags::WRITEABLE - PTEflags::NO_EXECUTE
+        } else if vm_addr >= code_end && vm_addr < rodata_end {
+            flags - PTEflags::WRITEABLE
+        } else {
+            flags
+        }
+    };
+
+    let mut counts = [0usize; 3];
+    let mut vm_addr = PAGE_SIZE;
+    while vm_addr < end {
+        let (i, size) = sizes
+            .iter()
+            .enumerate()
+            .find(|(_, size)| {
+                let len = size.bytes();
+                (**size != PageSize::Size1G || use_1g)
+                    && vm_addr % len == 0
+                    && vm_addr + len <= end
+                    && (**size == PageSize::Size4K || !needs_4k(vm_addr, len))
+            })
+            .unwrap();
+
+        space
+            .map_page(vm_addr, PhysAddr::new(vm_addr as u64), *size, kernel_flags(vm_addr))
+            .expect("map_kernel_identity: Kernel-Mapping fehlgeschlagen!");
+        counts[i] += 1;
+        vm_addr += size.bytes();
+    }
+    kprintln!(
+        "map_kernel_identity: {} x 1 GB, {} x 2 MB, {} x 4 KB Seiten bis 0x{:x}",
+        counts[0],
+        counts[1],
+        counts[2],
+        end
+    );
+}
+
+// Start- und Endadresse der Sektion '.user_text' im Kernel-Image (siehe 'linker.ld'),
+// beide Adressen sind 4 KB aligniert
+fn get_user_section() -> (usize, usize) {
+    unsafe {
+        (
+            &___USER_START__ as *const u64 as usize,
+            &___USER_END__ as *const u64 as usize,
+        )
+    }
+}
+
+// Kernel-Code (inkl. '.user_text') liegt in [code_start, code_end), die Konstanten
+// ('.rodata') in [code_end, rodata_end), danach folgen die Daten (siehe 'linker.ld')
+fn get_kernel_sections() -> (usize, usize, usize) {
+    unsafe {
+        (
+            &___KERNEL_DATA_START__ as *const u64 as usize & !(PAGE_SIZE - 1),
+            &___USER_END__ as *const u64 as usize,
+            &___RODATA_END__ as *const u64 as usize,
+        )
+    }
+}
+
+// Gibt 'nr_of_pages' bereits gemappte Seiten ab 'vm_start' im Kernel-Bereich fuer den
+// Ring 3 frei (User-Bit setzen). Nur fuer Code und Daten gedacht, die von User-Threads
+// genutzt werden muessen, z.B. 'kickoff_user_thread' und die Syscall-Wrapper.
+// Die Seiten muessen 4 KB gross sein (siehe 'map_kernel_identity').
+pub fn pg_share_with_user(pml4_addr: PhysAddr, vm_start: usize, nr_of_pages: usize) {
+    assert!(vm_start % PAGE_SIZE == 0, "pg_share_with_user: Adresse nicht aligniert!");
+
+    let space = AddressSpace::new(pml4_addr);
+    space.walk(vm_start, vm_start + nr_of_pages * PAGE_SIZE, |vm_addr, entry, size| {
+        assert!(size == PageSize::Size4K, "pg_share_with_user: grosse Seite an 0x{:x}!", vm_addr);
+        entry.set_flags(entry.get_flags() | PTEflags::USER);
+        if space.is_active() {
+            unsafe { x86::tlb::flush(vm_addr) };
+        }
+    });
+}
+
+// Bildet 'nr_of_pages' Seiten ab 'vm_start' im User-Bereich ab (genullte Page-Frames).
+// Bereits gemappte Seiten bleiben erhalten, z.B. wenn sich zwei ELF-Segmente eine
+// Seite teilen. 'writeable' = false -> Seiten nur lesbar, es sei denn sie waren
+// schon schreibbar gemappt. Entsprechend fuer 'executable' (sonst NO_EXECUTE).
+// Neue Page-Frames werden 'owner' zugerechnet.
+pub fn pg_mmap_user_range(
+    pml4_addr: PhysAddr,
+    vm_start: usize,
+    nr_of_pages: usize,
+    writeable: bool,
+    executable: bool,
+    owner: FrameOwner,
+) {
+    assert!(vm_start >= KERNEL_VM_SIZE, "pg_mmap_user_range: Adresse im Kernel-Bereich!");
+
+    let space = AddressSpace::new(pml4_addr);
+    let flags = PTEflags::for_user(true, writeable, executable);
+
+    for i in 0..nr_of_pages {
+        let vm_addr = vm_start + i * PAGE_SIZE;
+        if let Some((entry, _)) = space.pml4().find_leaf(vm_addr) {
+            let mut merged = entry.get_flags();
+            if writeable {
+                merged.insert(PTEflags::WRITEABLE);
+            }
+            if executable {
+                merged.remove(PTEflags::NO_EXECUTE);
+            }
+            if merged != entry.get_flags() {
+                space.protect(vm_addr, merged);
+            }
+            continue;
+        }
+
+        let frame = frames::pf_alloc(1, false, owner);
+        assert!(frame != PhysAddr(0), "Fehler: Keine freien Frames mehr!");
+        space
+            .map(vm_addr, frame, flags)
+            .expect("pg_mmap_user_range: Seite nicht mappbar!");
+    }
+}
+
+// Bildet die grosse Seite (2 MB) an 'vm_addr' im User-Bereich auf genullte,
+// entsprechend alignierte Page-Frames ab (fuer 'owner')
+pub fn pg_mmap_user_huge_page(pml4_addr: PhysAddr, vm_addr: usize, writeable: bool, executable: bool, owner: FrameOwner) {
+    assert!(vm_addr >= KERNEL_VM_SIZE, "pg_mmap_user_huge_page: Adresse im Kernel-Bereich!");
+
+    let size = PageSize::Size2M;
+    let frame = frames::pf_alloc_aligned(size.frames(), size.frames(), false, owner, true);
+    assert!(frame != PhysAddr(0), "Fehler: Keine freien Frames mehr!");
+
+    let flags = PTEflags::for_user(true, writeable, executable);
+    AddressSpace::new(pml4_addr)
+        .map_page(vm_addr, frame, size, flags)
+        .expect("pg_mmap_user_huge_page: Seite nicht mappbar!");
+}
+
+// Bildet 'nr_of_pages' Seiten ab 'vm_start' im User-Bereich auf die zusammenhaengenden
+// Page-Frames ab 'frames' ab (z.B. Shared Memory). Jeder Page-Frame bekommt eine weitere
+// Referenz, 'pg_unmap_user_range' gibt sie wieder auf.
+// 'user' = false -> Seiten sind im Ring 3 nicht zugreifbar (PROT_NONE)
+pub fn pg_map_shared_range(
+    pml4_addr: PhysAddr,
+    vm_start: usize,
+    frames: PhysAddr,
+    nr_of_pages: usize,
+    user: bool,
+    writeable: bool,
+    executable: bool,
+) {
+    assert!(vm_start >= KERNEL_VM_SIZE, "pg_map_shared_range: Adresse im Kernel-Bereich!");
+
+    let space = AddressSpace::new(pml4_addr);
+    let flags = PTEflags::for_user(user, writeable, executable);
+
+    for i in 0..nr_of_pages {
+        let frame = PhysAddr::new(frames.raw() + (i * PAGE_SIZE) as u64);
+        space
+            .map(vm_start + i * PAGE_SIZE, frame, flags)
+            .expect("pg_map_shared_range: Seite bereits gemappt!");
+        frames::pf_share(frame);
+    }
+}
+
+// Physikalische Adresse zu 'vm_addr' im Adressraum 'pml4_addr' ermitteln
+// Rueckgabe: None, falls die Seite nicht gemappt ist
+pub fn pg_translate(pml4_addr: PhysAddr, vm_addr: usize) -> Option<PhysAddr> {
+    AddressSpace::new(pml4_addr).translate(vm_addr)
+}
+
+// Entfernt 'nr_of_pages' Seiten ab 'vm_start' aus dem User-Bereich und gibt
+// deren Page-Frames frei (geteilte erst mit der letzten Referenz).
+// Die Tabellen bleiben bis 'pg_free_tables' erhalten.
+// Grosse Seiten muessen vollstaendig im Bereich liegen.
+pub fn pg_unmap_user_range(pml4_addr: PhysAddr, vm_start: usize, nr_of_pages: usize) {
+    assert!(vm_start >= KERNEL_VM_SIZE, "pg_unmap_user_range: Adresse im Kernel-Bereich!");
+
+    let space = AddressSpace::new(pml4_addr);
+    let mut present = Vec::new();
+    space.walk(vm_start, vm_start + nr_of_pages * PAGE_SIZE, |vm_addr, _, _| present.push(vm_addr));
+
+    for vm_addr in present {
+        if let Some((frame, size)) = space.unmap(vm_addr) {
+            frames::pf_release(frame, size.frames());
+        }
+    }
+}
+
+// Aendert die Rechte der bereits gemappten Seiten im Bereich 'vm_start' bis
+// 'vm_start + nr_of_pages * PAGE_SIZE'. Nicht gemappte Seiten werden ausgelassen,
+// sie bekommen die Rechte beim ersten Zugriff (siehe 'page_fault').
+// 'user' = false -> Seite ist im Ring 3 nicht zugreifbar (PROT_NONE)
+// Copy-on-Write-Seiten bleiben schreibgeschuetzt, bis sie kopiert wurden.
+pub fn pg_protect_user_range(
+    pml4_addr: PhysAddr,
+    vm_start: usize,
+    nr_of_pages: usize,
+    user: bool,
+    writeable: bool,
+    executable: bool,
+) {
+    assert!(vm_start >= KERNEL_VM_SIZE, "pg_protect_user_range: Adresse im Kernel-Bereich!");
+
+    let space = AddressSpace::new(pml4_addr);
+    space.walk(vm_start, vm_start + nr_of_pages * PAGE_SIZE, |vm_addr, entry, _| {
+        let mut flags = entry.get_flags();
+        flags.set(PTEflags::USER, user);
+        flags.set(PTEflags::WRITEABLE, writeable && !flags.contains(PTEflags::COW));
+        flags.remove(PTEflags::NO_EXECUTE);
+        if !executable {
+            flags |= PTEflags::no_execute();
+        }
+        entry.set_flags(flags);
+        if space.is_active() {
+            unsafe { x86::tlb::flush(vm_addr) };
+        }
+    });
+}
+
+// Teilt die gemappten Seiten im Bereich 'vm_start' bis 'vm_start + nr_of_pages * PAGE_SIZE'
+// von 'parent_pml4' mit 'child_pml4' (fuer 'fork'). Beide Adressraeume nutzen dieselben
+// Page-Frames, mit 'cow' werden alle Seiten in beiden als Copy-on-Write markiert, auch
+// gerade nicht schreibbare: sonst koennte ein spaeteres 'mprotect' sie schreibbar
+// machen und in den geteilten Page-Frame schreiben.
+pub fn pg_share_range(parent_pml4: PhysAddr, child_pml4: PhysAddr, vm_start: usize, nr_of_pages: usize, cow: bool) {
+    assert!(vm_start >= KERNEL_VM_SIZE, "pg_share_range: Adresse im Kernel-Bereich!");
+
+    let parent = AddressSpace::new(parent_pml4);
+    let child = AddressSpace::new(child_pml4);
+    parent.walk(vm_start, vm_start + nr_of_pages * PAGE_SIZE, |vm_addr, entry, size| {
+        let mut flags = entry.get_flags();
+        if cow {
+            flags.remove(PTEflags::WRITEABLE);
+            flags.insert(PTEflags::COW);
+            entry.set_flags(flags);
+            if parent.is_active() {
+                unsafe { x86::tlb::flush(vm_addr) };
+            }
+        }
+        frames::pf_share(entry.get_addr());
+        child
+            .map_page(vm_addr, entry.get_addr(), size, flags - PTEflags::ACCESSED - PTEflags::DIRTY)
+            .expect("pg_share_range: Seite im Kind bereits gemappt!");
+    });
+}
+
+// Schreibzugriff auf eine Copy-on-Write-Seite aufloesen: ist der Page-Frame noch
+// geteilt, bekommt der Adressraum eine eigene Kopie, sonst wird die Seite nur
+// wieder schreibbar.
+// Rueckgabe: false, falls 'vm_addr' nicht in einer Copy-on-Write-Seite liegt
+pub fn pg_resolve_cow(pml4_addr: PhysAddr, vm_addr: usize) -> bool {
+    let space = AddressSpace::new(pml4_addr);
+    let (entry, size) = match space.pml4().find_leaf(vm_addr) {
+        Some((entry, size)) if entry.get_flags().contains(PTEflags::COW) => (entry, size),
+        _ => return false,
+    };
+    let page = vm_addr & !(size.bytes() - 1);
+    let old_frame = entry.get_addr();
+
+    if frames::pf_ref_count(old_frame) > 1 {
+        // Inhalt wird sofort vollstaendig ueberschrieben, Nullen unnoetig
+        let owner = frames::pf_owner(old_frame);
+        let new_frame = if size == PageSize::Size4K {
+            frames::pf_alloc_uninit(1, false, owner)
+        } else {
+            frames::pf_alloc_aligned(size.frames(), size.frames(), false, owner, false)
+        };
+        assert!(new_frame != PhysAddr(0), "Fehler: Keine freien Frames mehr!");
+        unsafe {
+            ptr::copy_nonoverlapping(old_frame.as_ptr::<u8>(), new_frame.as_mut_ptr::<u8>(), size.bytes());
+        }
+        entry.set_addr(new_frame);
+        frames::pf_release(old_frame, size.frames());
+    }
+
+    space.protect(page, (entry.get_flags() | PTEflags::WRITEABLE) - PTEflags::COW);
+    true
 }
 
 // Setze das CR3 Register
@@ -663,3 +900,72 @@ pub fn pg_set_cr3(pml4_addr: PhysAddr) {
     PageTable::set_cr3(pml4_addr);
 }
 
+// Gibt die Page-Tables des User-Bereichs (ab KERNEL_VM_SIZE) und die PML4 eines
+// Adressraums frei, ebenso die Page-Frames hinter den User-Eintraegen. Die
+// gemeinsamen Kernel-Tabellen bleiben erhalten.
+// Darf nicht fuer den gerade aktiven Adressraum (CR3) gerufen werden.
+pub fn pg_free_tables(pml4_addr: PhysAddr) {
+    assert!(pml4_addr != PhysAddr(0));
+    assert!(pml4_addr != PageTable::get_cr3(), "pg_free_tables: Adressraum ist noch aktiv!");
+    assert!(pml4_addr.raw() != KERNEL_PML4.load(Ordering::SeqCst), "pg_free_tables: Kernel-Tabellen!");
+
+    let pml4_table = unsafe { &mut *(pml4_addr.as_mut_ptr::<PageTable>()) };
+
+    for entry in pml4_table.entries[FIRST_USER_PML4_INDEX..].iter() {
+        if entry.is_present() {
+            free_sub_tables(entry.get_addr(), 3);
+        }
+    }
+    frames::pf_free(pml4_addr, 1);
+}
+
+// Hilfsfunktion von 'pg_free_tables': gibt die Tabelle 'table_addr' der Ebene
+// 'level' (3 = PDPT, 2 = PD, 1 = PT), alle darunterliegenden Tabellen und die
+// gemappten Page-Frames frei
+fn free_sub_tables(table_addr: PhysAddr, level: usize) {
+    let table = unsafe { &mut *(table_addr.as_mut_ptr::<PageTable>()) };
+
+    for entry in table.entries.iter() {
+        if !entry.is_present() {
+            continue;
+        }
+        if level > 1 && !entry.get_flags().contains(PTEflags::HUGE_PAGE) {
+            free_sub_tables(entry.get_addr(), level - 1);
+        } else {
+            frames::pf_release(entry.get_addr(), PageSize::from_level(level).frames());
+        }
+    }
+    frames::pf_free(table_addr, 1);
+}
+
+// Zaehlt im User-Bereich eines Adressraums die gemappten Page-Frames (geteilte
+// Frames zaehlen in jedem Adressraum) und die Page-Tables inklusive PML4
+// Rueckgabe: (Page-Frames, Page-Tables)
+pub fn pg_count_user_frames(pml4_addr: PhysAddr) -> (usize, usize) {
+    let pml4_table = unsafe { &*(pml4_addr.as_ptr::<PageTable>()) };
+    let mut counts = (0, 1);
+
+    for entry in pml4_table.entries[FIRST_USER_PML4_INDEX..].iter() {
+        if entry.is_present() {
+            count_sub_tables(entry.get_addr(), 3, &mut counts);
+        }
+    }
+    counts
+}
+
+// Hilfsfunktion von 'pg_count_user_frames', analog zu 'free_sub_tables'
+fn count_sub_tables(table_addr: PhysAddr, level: usize, counts: &mut (usize, usize)) {
+    let table = unsafe { &*(table_addr.as_ptr::<PageTable>()) };
+    counts.1 += 1;
+
+    for entry in table.entries.iter() {
+        if !entry.is_present() {
+            continue;
+        }
+        if level > 1 && !entry.get_flags().contains(PTEflags::HUGE_PAGE) {
+            count_sub_tables(entry.get_addr(), level - 1, counts);
+        } else {
+            counts.0 += PageSize::from_level(level).frames();
+        }
+    }
+}
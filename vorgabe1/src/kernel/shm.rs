@@ -0,0 +1,170 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: shm                                                             ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Shared memory objects for exchanging data between processes.    ║
+   ║         An object is a block of zeroed page frames, identified by an id ║
+   ║         (handle). Objects created with a key != 0 can also be found by  ║
+   ║         other processes with the same key.                              ║
+   ║                                                                         ║
+   ║         Each process which created or looked up an object holds a      ║
+   ║         handle on it until it exits ('Process::add_shm_handle'), each   ║
+   ║         mapping (see 'Process::map_shm') counts as a further reference, ║
+   ║         'fork' adds references for the child. With the last reference   ║
+   ║         the page frames are freed and the id becomes invalid.           ║
+   ║                                                                         ║
+   ║         The object owns one reference on each page frame ('pf_alloc'),  ║
+   ║         every page table entry mapping it owns another ('pf_share').    ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+use crate::consts::PAGE_SIZE;
+use crate::kernel::paging::frames::FrameOwner;
+use crate::kernel::cpu;
+use crate::kernel::paging::frames;
+use crate::kernel::paging::frames::PhysAddr;
+
+// Groesste Laenge eines Objekts in Bytes
+pub const MAX_SIZE: usize = 0x100_0000;
+
+// Warum 'create' fehlschlug
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ShmError {
+    Invalid,  // Laenge 0, zu gross oder groesser als das Objekt mit demselben 'key'
+    NoMemory, // keine ausreichend grossen freien Page-Frames
+}
+
+struct ShmObject {
+    key: u64,
+    frames: PhysAddr, // erster Page-Frame, alle zusammenhaengend
+    nr_of_pages: usize,
+    refs: usize,      // Handles und Mappings
+}
+
+struct ShmTable {
+    objects: BTreeMap<usize, ShmObject>,
+    next_id: usize,
+}
+
+static SHM_TABLE: Mutex<ShmTable> = Mutex::new(ShmTable {
+    objects: BTreeMap::new(),
+    next_id: 1,
+});
+
+// 'f' mit gesperrter Tabelle ausfuehren (Interrupts aus, 'release' laeuft auch in 'Process::drop')
+fn with_table<R>(f: impl FnOnce(&mut ShmTable) -> R) -> R {
+    let irq = cpu::disable_int_nested();
+    let result = f(&mut SHM_TABLE.lock());
+    cpu::enable_int_nested(irq);
+    result
+}
+
+/**
+    Description: Create a shared memory object with `size` bytes (rounded up to 4 KB). \
+                 If `key` is not 0 and an object with this key exists already, \
+                 this object is returned instead. The caller gets a reference \
+                 (handle), which must be dropped with `release`.
+
+    Return: \
+           id of the object or the reason why it could not be created
+*/
+pub fn create(key: u64, size: usize) -> Result<usize, ShmError> {
+    if size == 0 || size > MAX_SIZE {
+        return Err(ShmError::Invalid);
+    }
+    let nr_of_pages = size.div_ceil(PAGE_SIZE);
+
+    // Page-Frames vor dem Sperren der Tabelle holen (sind genullt)
+    let frames = frames::pf_alloc(nr_of_pages, false, FrameOwner::Shm);
+    if frames == PhysAddr(0) {
+        return Err(ShmError::NoMemory);
+    }
+
+    // Suche nach 'key' und Eintragen unter einer Sperre
+    let result = with_table(|t| {
+        if key != 0 {
+            let existing = t.objects.iter_mut().find(|(_, o)| o.key == key);
+            if let Some((id, obj)) = existing {
+                if nr_of_pages > obj.nr_of_pages {
+                    return Err(ShmError::Invalid);
+                }
+                obj.refs += 1;
+                return Ok((*id, false));
+            }
+        }
+        let id = t.next_id;
+        t.next_id += 1;
+        t.objects.insert(
+            id,
+            ShmObject {
+                key,
+                frames,
+                nr_of_pages,
+                refs: 1,
+            },
+        );
+        Ok((id, true))
+    });
+
+    match result {
+        Ok((id, true)) => {
+            kprintln!("shm::create: id={}, key={}, {} pages at 0x{:x}", id, key, nr_of_pages, frames.raw());
+            Ok(id)
+        }
+        Ok((id, false)) => {
+            frames::pf_free(frames, nr_of_pages);
+            Ok(id)
+        }
+        Err(e) => {
+            frames::pf_free(frames, nr_of_pages);
+            Err(e)
+        }
+    }
+}
+
+/**
+    Description: Add a mapping reference to the object `id`, must be followed by \
+                 `Process::map_shm` or `release`.
+
+    Return: \
+           first page frame and length in bytes, or `None` if there is no such object
+*/
+pub fn acquire(id: usize) -> Option<(PhysAddr, usize)> {
+    with_table(|t| {
+        let obj = t.objects.get_mut(&id)?;
+        obj.refs += 1;
+        Some((obj.frames, obj.nr_of_pages * PAGE_SIZE))
+    })
+}
+
+// Weitere Referenz auf ein Objekt, von dem der Aufrufer schon eine haelt (fuer 'fork')
+pub fn retain(id: usize) {
+    with_table(|t| {
+        t.objects.get_mut(&id).expect("shm::retain: unbekanntes Objekt").refs += 1;
+    });
+}
+
+/**
+    Description: Drop a reference (handle or mapping) of the object `id`. With the \
+                 last one the object is removed and its page frames are released.
+*/
+pub fn release(id: usize) {
+    let removed = with_table(|t| {
+        let obj = t.objects.get_mut(&id).expect("shm::release: unbekanntes Objekt");
+        obj.refs -= 1;
+        if obj.refs == 0 {
+            t.objects.remove(&id)
+        } else {
+            None
+        }
+    });
+
+    // Frames ausserhalb der Sperre freigeben, noch gemappte Seiten halten eigene Referenzen
+    if let Some(obj) = removed {
+        kprintln!("shm::release: id={} freed", id);
+        for i in 0..obj.nr_of_pages {
+            frames::pf_release(PhysAddr::new(obj.frames.raw() + (i * PAGE_SIZE) as u64), 1);
+        }
+    }
+}
@@ -5,3 +5,17 @@ pub mod sys_getlastkey;
 pub mod sys_gettid;
 pub mod sys_read;
 pub mod sys_write;
+pub mod sys_exit;
+pub mod sys_join;
+pub mod sys_sleep;
+pub mod sys_thread_stats;
+pub mod sys_sbrk;
+pub mod sys_mmap;
+pub mod sys_munmap;
+pub mod sys_mprotect;
+pub mod sys_fork;
+pub mod sys_shm_create;
+pub mod sys_shm_map;
+pub mod sys_shm_unmap;
+pub mod sys_mem_stats;
+pub mod sys_set_priority;
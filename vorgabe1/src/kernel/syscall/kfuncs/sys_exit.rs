@@ -0,0 +1,8 @@
+use crate::kernel::syscall::user_api::SYSNO_EXIT;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_exit(exit_code: i64) {
+   // Kehrt nicht zurueck, der Thread wird zum Zombie
+   scheduler::Scheduler::exit(exit_code);
+}
@@ -0,0 +1,22 @@
+use crate::kernel::syscall::user_api::ESRCH;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::thread::Thread;
+
+#[no_mangle]
+pub extern "C" fn sys_fork() -> i64 {
+   // Rueckgabe: pid des Kindes (im Kind 0, siehe '_thread_fork_start') oder -ESRCH
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let parent_tid = scheduler::get_active_tid();
+   let context = scheduler::get_active_syscall_context();
+
+   let child_tid = scheduler::next_thread_id();
+   let child = process.lock().fork(parent_tid, child_tid);
+   let child_pid = child.lock().get_pid();
+
+   Scheduler::ready(Thread::new_forked(&child, child_tid, context));
+   child_pid as i64
+}
@@ -0,0 +1,18 @@
+use crate::kernel::syscall::user_api::{EFAULT, ESRCH, SYSNO_JOIN};
+use crate::kernel::syscall::user_copy::UserPtr;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_join(tid: u64, exit_code: u64) -> i64 {
+   // Wartet bis Thread 'tid' beendet ist
+   let exit_code: UserPtr<i64> = UserPtr::new(exit_code);
+   match scheduler::Scheduler::join(tid as usize) {
+      Some(code) => {
+         if !exit_code.is_null() && exit_code.write(&code).is_err() {
+            return -EFAULT;
+         }
+         0
+      }
+      None => -ESRCH,
+   }
+}
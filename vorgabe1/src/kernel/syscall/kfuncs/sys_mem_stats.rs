@@ -0,0 +1,21 @@
+use crate::kernel::paging::frames;
+use crate::kernel::paging::frames::MemStats;
+use crate::kernel::syscall::user_api::EFAULT;
+use crate::kernel::syscall::user_copy::UserPtr;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_mem_stats(stats: u64) -> i64 {
+   // Speicherstatistik inkl. der Page-Frames des aufrufenden Prozesses kopieren
+   let stats: UserPtr<MemStats> = UserPtr::new(stats);
+   let mut s = frames::pf_stats();
+   if let Some(process) = scheduler::get_active_process() {
+      let (pages, tables) = process.lock().count_frames();
+      s.process_pages = pages as u64;
+      s.process_tables = tables as u64;
+   }
+   if stats.is_null() || stats.write(&s).is_err() {
+      return -EFAULT;
+   }
+   0
+}
@@ -0,0 +1,28 @@
+use crate::kernel::paging::pages::PageSize;
+use crate::kernel::syscall::user_api::{EINVAL, ENOMEM, ESRCH, MAP_ANONYMOUS, MAP_FIXED, MAP_HUGETLB};
+use crate::kernel::threads::process::VmProt;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_mmap(addr: u64, len: u64, prot: u64, flags: u64) -> i64 {
+   // Nur anonyme Bereiche, Rueckgabe: Startadresse, -EINVAL, -ENOMEM oder -ESRCH
+   let prot = match VmProt::from_bits(prot) {
+      Some(prot) => prot,
+      None => return -EINVAL,
+   };
+   if flags & MAP_ANONYMOUS == 0 || flags & !(MAP_ANONYMOUS | MAP_FIXED | MAP_HUGETLB) != 0 {
+      return -EINVAL;
+   }
+   let addr = if flags & MAP_FIXED != 0 { Some(addr as usize) } else { None };
+   let page_size = if flags & MAP_HUGETLB != 0 { PageSize::Size2M } else { PageSize::Size4K };
+
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let result = process.lock().mmap(addr, len as usize, prot, page_size);
+   match result {
+      Some(start) => start as i64,
+      None => -ENOMEM,
+   }
+}
@@ -0,0 +1,23 @@
+use crate::kernel::syscall::user_api::{EINVAL, ENOMEM, ESRCH};
+use crate::kernel::threads::process::VmProt;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_mprotect(addr: u64, len: u64, prot: u64) -> i64 {
+   // Zugriffsrechte aendern, Rueckgabe: 0, -EINVAL, -ENOMEM oder -ESRCH
+   let prot = match VmProt::from_bits(prot) {
+      Some(prot) => prot,
+      None => return -EINVAL,
+   };
+
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let ok = process.lock().mprotect(addr as usize, len as usize, prot);
+   if ok {
+      0
+   } else {
+      -ENOMEM
+   }
+}
@@ -0,0 +1,17 @@
+use crate::kernel::syscall::user_api::{EINVAL, ESRCH};
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_munmap(addr: u64, len: u64) -> i64 {
+   // Bereiche aus 'mmap' entfernen, Rueckgabe: 0, -EINVAL oder -ESRCH
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let ok = process.lock().munmap(addr as usize, len as usize);
+   if ok {
+      0
+   } else {
+      -EINVAL
+   }
+}
@@ -1,22 +1,27 @@
-use crate::kernel::syscall::user_api::SYSNO_READ;
-use crate::kernel::syscall::user_api::syscall0;
+use crate::kernel::syscall::user_api::EFAULT;
+use crate::kernel::syscall::user_copy;
+use crate::kernel::threads::process::VmProt;
+use crate::mylib::input::getch;
+
+const KEY_LF: u8 = 10;
 
 #[no_mangle]
 pub extern "C" fn sys_read(buff: *mut u8, len: u64) -> i64{
-   //Teste das schreiben in 
-   let mut bytes_read: u64 = 0;
-   let text = b"read-text-successfully-stored-test";
-   let text_len = text.len() as u64;
+   // Zeichen von der Tastatur lesen, bis 'len' Bytes gelesen sind oder Enter gedrueckt wurde
+   if user_copy::access_ok(buff as usize, len as usize, VmProt::WRITE).is_err() {
+      return -EFAULT;
+   }
 
-   if len > text_len{ //check ob überhaupt genug Platz
-      for i in 0..text_len { // zu len ändern wenn Funktionalität kommt
-         unsafe {
-            *buff.add(i as usize) = text[i as usize];
-         }
+   let mut bytes_read: u64 = 0;
+   while bytes_read < len {
+      let key = getch();
+      if user_copy::copy_to_user(buff as usize + bytes_read as usize, &[key]).is_err() {
+         return -EFAULT;
+      }
       bytes_read += 1;
+      if key == KEY_LF {
+         break;
       }
    }
-
-   // Was zurückgeben??? Anzahl geschriebener Bytes als Kontrolle???
    bytes_read as i64
 }
@@ -0,0 +1,16 @@
+use crate::kernel::syscall::user_api::{ENOMEM, ESRCH};
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_sbrk(increment: i64) -> i64 {
+   // Heap des Prozesses verschieben, Rueckgabe: altes Ende, -ENOMEM oder -ESRCH
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let result = process.lock().sbrk(increment as isize);
+   match result {
+      Some(old_brk) => old_brk as i64,
+      None => -ENOMEM,
+   }
+}
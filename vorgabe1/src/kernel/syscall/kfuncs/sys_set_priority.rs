@@ -0,0 +1,14 @@
+use crate::kernel::syscall::user_api::EINVAL;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::thread;
+
+#[no_mangle]
+pub extern "C" fn sys_set_priority(priority: u64) -> i64 {
+   // Prioritaet des Aufrufers setzen, Rueckgabe: 0 oder -EINVAL
+   let priority = priority as usize;
+   if priority == thread::IDLE_PRIORITY || priority >= thread::PRIO_LEVELS {
+      return -EINVAL;
+   }
+   scheduler::Scheduler::set_priority(priority);
+   0
+}
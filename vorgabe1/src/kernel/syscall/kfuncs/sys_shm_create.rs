@@ -0,0 +1,25 @@
+use crate::kernel::shm;
+use crate::kernel::shm::ShmError;
+use crate::kernel::syscall::user_api::{EINVAL, ENOMEM, ESRCH};
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_shm_create(key: u64, size: u64) -> i64 {
+   // Rueckgabe: id des Objekts, -EINVAL, -ENOMEM oder -ESRCH
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   match shm::create(key, size as usize) {
+      Ok(id) => {
+         // Handle gehoert dem Prozess, eine doppelte Referenz wieder abgeben
+         let added = process.lock().add_shm_handle(id);
+         if !added {
+            shm::release(id);
+         }
+         id as i64
+      }
+      Err(ShmError::Invalid) => -EINVAL,
+      Err(ShmError::NoMemory) => -ENOMEM,
+   }
+}
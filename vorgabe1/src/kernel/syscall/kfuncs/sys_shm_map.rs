@@ -0,0 +1,35 @@
+use crate::kernel::shm;
+use crate::kernel::syscall::user_api::{EINVAL, ESRCH};
+use crate::kernel::threads::process::VmProt;
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_shm_map(id: u64, addr: u64, prot: u64) -> i64 {
+   // Objekt einblenden, 'addr' = 0 -> Kernel waehlt, Rueckgabe: Startadresse, -EINVAL oder -ESRCH
+   let prot = match VmProt::from_bits(prot) {
+      Some(prot) => prot,
+      None => return -EINVAL,
+   };
+   let addr = if addr != 0 { Some(addr as usize) } else { None };
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+
+   // Nur Objekte, auf die der Prozess ein Handle haelt (aus 'usr_shm_create')
+   if !process.lock().has_shm_handle(id as usize) {
+      return -EINVAL;
+   }
+   let (frames, len) = match shm::acquire(id as usize) {
+      Some(obj) => obj,
+      None => return -EINVAL,
+   };
+   let result = process.lock().map_shm(addr, id as usize, frames, len, prot);
+   match result {
+      Some(start) => start as i64,
+      None => {
+         shm::release(id as usize);
+         -EINVAL
+      }
+   }
+}
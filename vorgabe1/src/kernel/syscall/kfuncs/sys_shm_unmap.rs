@@ -0,0 +1,20 @@
+use crate::kernel::shm;
+use crate::kernel::syscall::user_api::{EINVAL, ESRCH};
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_shm_unmap(addr: u64) -> i64 {
+   // Rueckgabe: 0, -EINVAL oder -ESRCH
+   let process = match scheduler::get_active_process() {
+      Some(process) => process,
+      None => return -ESRCH,
+   };
+   let result = process.lock().unmap_shm(addr as usize);
+   match result {
+      Some(id) => {
+         shm::release(id);
+         0
+      }
+      None => -EINVAL,
+   }
+}
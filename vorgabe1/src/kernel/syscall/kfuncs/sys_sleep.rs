@@ -0,0 +1,7 @@
+use crate::kernel::threads::scheduler;
+
+#[no_mangle]
+pub extern "C" fn sys_sleep(ms: u64) {
+   // Aufrufer blockiert, bis die Zeit abgelaufen ist (PIT weckt ihn)
+   scheduler::Scheduler::sleep_ms(ms);
+}
@@ -0,0 +1,19 @@
+use crate::kernel::syscall::user_api::{EFAULT, ESRCH};
+use crate::kernel::syscall::user_copy::UserPtr;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::thread::ThreadStats;
+
+#[no_mangle]
+pub extern "C" fn sys_thread_stats(tid: u64, stats: u64) -> i64 {
+   // CPU-Verbrauch von Thread 'tid' in den Puffer des Aufrufers kopieren
+   let stats: UserPtr<ThreadStats> = UserPtr::new(stats);
+   match scheduler::Scheduler::get_stats(tid as usize) {
+      Some(s) => {
+         if stats.is_null() || stats.write(&s).is_err() {
+            return -EFAULT;
+         }
+         0
+      }
+      None => -ESRCH,
+   }
+}
@@ -1,17 +1,29 @@
-use crate::kernel::syscall::user_api::SYSNO_WRITE;
-use crate::kernel::syscall::user_api::syscall0;
+use crate::kernel::syscall::user_api::EFAULT;
+use crate::kernel::syscall::user_copy;
+use crate::kernel::threads::process::VmProt;
 
 #[no_mangle]
 pub extern "C" fn sys_write(buff: *const u8, len: u64) -> i64{
+   // Gesamten Puffer pruefen, bevor etwas ausgegeben wird
+   if user_copy::access_ok(buff as usize, len as usize, VmProt::READ).is_err() {
+      return -EFAULT;
+   }
+
    // Lauf-Variable für die bereits ausgegebenen chars 
    let mut bytes_written: u64 = 0;
 
-   for i in 0..len {
-       let byte = unsafe { *buff.add(i as usize) };
-       kprint!("{}", byte as char);
-       bytes_written += 1;
+   // User-Puffer stueckweise in den Kernel kopieren, nie direkt lesen
+   let mut chunk = [0u8; 64];
+   while bytes_written < len {
+      let n = (len - bytes_written).min(chunk.len() as u64) as usize;
+      if user_copy::copy_from_user(&mut chunk[..n], buff as usize + bytes_written as usize).is_err() {
+         return -EFAULT;
+      }
+      for byte in chunk[..n].iter() {
+         kprint!("{}", *byte as char);
+      }
+      bytes_written += n as u64;
    }
    kprint!("\n");
-   // Was zurückgeben??? Anzahl geschriebener Bytes als Kontrolle???
    bytes_written as i64
 }
@@ -16,3 +16,4 @@
 pub mod user_api;
 pub mod syscall_dispatcher;
 pub mod kfuncs;
+pub mod user_copy;
@@ -10,7 +10,7 @@
  * Autor:           Stefan Lankes, RWTH Aachen                               *
  *                  Michael Schoettner, 23.10.2024, modifiziert              *
  *****************************************************************************/
-use core::arch::{asm, naked_asm};
+use core::arch::naked_asm;
 
 use crate::kernel::syscall;
 use crate::kernel::syscall::kfuncs::sys_getlastkey::sys_getlastkey;
@@ -18,7 +18,22 @@ use crate::kernel::syscall::kfuncs::sys_gettid::sys_gettid;
 use crate::kernel::syscall::kfuncs::sys_hello_world::sys_hello_world;
 use crate::kernel::syscall::kfuncs::sys_read::sys_read;
 use crate::kernel::syscall::kfuncs::sys_write::sys_write;
+use crate::kernel::syscall::kfuncs::sys_exit::sys_exit;
+use crate::kernel::syscall::kfuncs::sys_join::sys_join;
+use crate::kernel::syscall::kfuncs::sys_sleep::sys_sleep;
+use crate::kernel::syscall::kfuncs::sys_thread_stats::sys_thread_stats;
+use crate::kernel::syscall::kfuncs::sys_sbrk::sys_sbrk;
+use crate::kernel::syscall::kfuncs::sys_mmap::sys_mmap;
+use crate::kernel::syscall::kfuncs::sys_munmap::sys_munmap;
+use crate::kernel::syscall::kfuncs::sys_mprotect::sys_mprotect;
+use crate::kernel::syscall::kfuncs::sys_fork::sys_fork;
+use crate::kernel::syscall::kfuncs::sys_shm_create::sys_shm_create;
+use crate::kernel::syscall::kfuncs::sys_shm_map::sys_shm_map;
+use crate::kernel::syscall::kfuncs::sys_shm_unmap::sys_shm_unmap;
+use crate::kernel::syscall::kfuncs::sys_mem_stats::sys_mem_stats;
+use crate::kernel::syscall::kfuncs::sys_set_priority::sys_set_priority;
 use crate::kernel::syscall::user_api;
+use crate::kernel::threads::scheduler;
 
 extern "C" {
     fn _init_syscalls();
@@ -50,6 +65,20 @@ impl SyscallFuncTable {
                 sys_read as *const _,
                 sys_getlastkey as *const _,
                 sys_gettid as *const _,
+                sys_exit as *const _,
+                sys_join as *const _,
+                sys_sleep as *const _,
+                sys_thread_stats as *const _,
+                sys_sbrk as *const _,
+                sys_mmap as *const _,
+                sys_munmap as *const _,
+                sys_mprotect as *const _,
+                sys_fork as *const _,
+                sys_shm_create as *const _,
+                sys_shm_map as *const _,
+                sys_shm_unmap as *const _,
+                sys_mem_stats as *const _,
+                sys_set_priority as *const _,
             ],
         }
     }
@@ -80,16 +109,15 @@ pub unsafe extern "C" fn syscall_disp() {
  * Funktion:        syscall_abort                                            *
  *---------------------------------------------------------------------------*
  * Beschreibung:    Falls eine unbekannte Funktionsnummer verwendet wurde,   *
- *                  ruft der Assembler-Code diese Funktion auf, um eine      *
- *                  panic auszuloesen.                                       *
+ *                  ruft der Assembler-Code diese Funktion auf. Der Aufrufer *
+ *                  erhaelt -ENOSYS in rax, das System laeuft weiter.        *
  *****************************************************************************/
  #[no_mangle]
-pub unsafe extern "C" fn syscall_abort() {
-    let sys_no: u64;
-
-    asm!(
-        "mov {}, rax", out(reg) sys_no
+pub extern "C" fn syscall_abort(sys_no: u64) -> i64 {
+    kprintln!(
+        "Systemaufruf mit Nummer {} existiert nicht (tid={})",
+        sys_no,
+        scheduler::get_active_tid()
     );
-
-    panic!("Systemaufruf mit Nummer {} existiert nicht!", sys_no);
+    -user_api::ENOSYS
 }
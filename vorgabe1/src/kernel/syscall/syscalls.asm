@@ -16,7 +16,7 @@
 
 [EXTERN _idt]                 ; IDT in 'interrupts.asm' 
 [EXTERN syscall_disp]         ; Funktion in Rust, die Syscalls behandelt
-[EXTERN syscall_abort]        ; Funktion in Rust, die abbricht, 
+[EXTERN syscall_abort]        ; Funktion in Rust, die -ENOSYS liefert,
                               ; falls der Systemaufruf nicht existiert
 
 [SECTION .text]
@@ -24,7 +24,7 @@
 
 ; Hoechste Funktionsnummer für den System-Aufruf-Dispatcher
 ; Muss mit NO_SYSCALLS in 'kernel/syscall/usr_api.rs' konsistent sein!
-NO_SYSCALLS: equ 5
+NO_SYSCALLS: equ 19
 
 ; Vektor fuer Systemaufrufe
 SYSCALL_TRAPGATE: equ 0x80
@@ -135,12 +135,24 @@ _syscall_handler:
 
 
 	; Pruefen, ob die Funktionsnummer nicht zu gross ist
+	; (vorzeichenlos, damit auch negative Nummern abgewiesen werden)
 	cmp rax, NO_SYSCALLS
-	jge syscall_abort   ; wirft eine Panic, kehrt nicht zurueck
+	jb _syscall_valid
+
+	; Unbekannte Nummer -> 'syscall_abort' liefert -ENOSYS in rax
+	mov rdi, rax
+	call syscall_abort
+	jmp _syscall_return
+
+_syscall_valid:
+	; 4. Parameter kommt in r10 (rcx wurde oben fuer DS/ES genutzt),
+	; die Rust-Funktion erwartet ihn in rcx
+	mov rcx, r10
 
 	; Funktionsnummer ist OK -> Rust aufrufen
 	call syscall_disp
 
+_syscall_return:
  	; DS und ES wiederherstellen
 	pop 	rcx			;;// DS + ES liegen auf Stack 
 	mov 	ES, cx		;;// ES als letztes drauf => erstes runter
@@ -16,11 +16,14 @@
 
 use core::arch::asm;
 
+use crate::kernel::paging::frames::MemStats;
+use crate::kernel::threads::thread::ThreadStats;
+
 
 
 // Anzahl an Systemaufrufen
 // Muss mit NO_SYSCALLS in 'kernel/syscall/syscalls.asm' konsistent sein!
-pub const NO_SYSCALLS: usize = 5;
+pub const NO_SYSCALLS: usize = 19;
 
 // Funktionsnummern aller Systemaufrufe
 pub const SYSNO_HELLO_WORLD: usize = 0;
@@ -28,30 +31,168 @@ pub const SYSNO_WRITE: usize = 1;
 pub const SYSNO_READ: usize = 2;
 pub const SYSNO_GETLASTKEY: usize = 3;
 pub const SYSNO_GETTID: usize = 4;
+pub const SYSNO_EXIT: usize = 5;
+pub const SYSNO_JOIN: usize = 6;
+pub const SYSNO_SLEEP: usize = 7;
+pub const SYSNO_THREAD_STATS: usize = 8;
+pub const SYSNO_SBRK: usize = 9;
+pub const SYSNO_MMAP: usize = 10;
+pub const SYSNO_MUNMAP: usize = 11;
+pub const SYSNO_MPROTECT: usize = 12;
+pub const SYSNO_FORK: usize = 13;
+pub const SYSNO_SHM_CREATE: usize = 14;
+pub const SYSNO_SHM_MAP: usize = 15;
+pub const SYSNO_SHM_UNMAP: usize = 16;
+pub const SYSNO_MEM_STATS: usize = 17;
+pub const SYSNO_SET_PRIORITY: usize = 18;
+
+// Fehlercodes: Systemaufrufe liefern im Fehlerfall den negativen Wert in rax
+// (wie bei Linux), z.B. -EFAULT fuer einen ungueltigen Puffer
+pub const ESRCH: i64 = 3; // Thread existiert nicht
+pub const ENOMEM: i64 = 12; // kein Speicher bzw. Bereich nicht eingeblendet
+pub const EFAULT: i64 = 14; // Puffer liegt nicht im eingeblendeten User-Speicher
+pub const EINVAL: i64 = 22; // ungueltiges Argument
+pub const ENOSYS: i64 = 38; // unbekannte Funktionsnummer
+
+// Zugriffsrechte fuer 'usr_mmap' und 'usr_mprotect'
+pub const PROT_NONE: u64 = 0;
+pub const PROT_READ: u64 = 1 << 0;
+pub const PROT_WRITE: u64 = 1 << 1;
+pub const PROT_EXEC: u64 = 1 << 2;
+
+// Flags fuer 'usr_mmap' (nur anonyme Bereiche werden unterstuetzt)
+pub const MAP_FIXED: u64 = 0x10;
+pub const MAP_ANONYMOUS: u64 = 0x20;
+pub const MAP_HUGETLB: u64 = 0x40000; // mit 2 MB Seiten unterlegen
 
 /* 
  * Hier muss Code eingefuegt werden 
  */
 
+// Die Wrapper werden im Ring 3 ausgefuehrt und liegen daher in der Sektion
+// '.user_text' (siehe 'linker.ld'). Die 'syscallN'-Funktionen werden immer
+// eingebettet und benoetigen keine eigene Sektion.
 
+#[link_section = ".user_text"]
 pub fn usr_hello_world() {
    syscall0(SYSNO_HELLO_WORLD as u64);
 }
 
+#[link_section = ".user_text"]
 pub fn usr_getlastkey() {
     syscall0(SYSNO_GETLASTKEY as u64);
 }
 
+#[link_section = ".user_text"]
 pub fn usr_gettid() {
     syscall0(SYSNO_GETTID as u64);
 }
 
-pub fn usr_read(buff: *mut u8, len: u64) {
-    syscall2(SYSNO_READ as u64, buff as u64, len);
+// Bis zu 'len' Zeichen von der Tastatur lesen (endet nach Enter)
+// Rueckgabe: Anzahl gelesener Bytes, oder -EFAULT
+#[link_section = ".user_text"]
+pub fn usr_read(buff: *mut u8, len: u64) -> i64 {
+    syscall2(SYSNO_READ as u64, buff as u64, len) as i64
+}
+
+// Rueckgabe: Anzahl ausgegebener Bytes, oder -EFAULT
+#[link_section = ".user_text"]
+pub fn usr_write(buff: *const u8, len: u64) -> i64 {
+    syscall2(SYSNO_WRITE as u64, buff as u64, len) as i64
+}
+
+#[link_section = ".user_text"]
+pub fn usr_exit(exit_code: i64) -> ! {
+    syscall1(SYSNO_EXIT as u64, exit_code as u64);
+    loop {}
+}
+
+// Rueckgabe: 0 und Exit-Code in 'exit_code', -ESRCH falls es 'tid' nicht gibt, oder -EFAULT
+#[link_section = ".user_text"]
+pub fn usr_join(tid: u64, exit_code: *mut i64) -> i64 {
+    syscall2(SYSNO_JOIN as u64, tid, exit_code as u64) as i64
+}
+
+#[link_section = ".user_text"]
+pub fn usr_sleep(ms: u64) {
+    syscall1(SYSNO_SLEEP as u64, ms);
+}
+
+// Rueckgabe: 0 und CPU-Verbrauch in 'stats', -ESRCH falls es 'tid' nicht gibt, oder -EFAULT
+#[link_section = ".user_text"]
+pub fn usr_thread_stats(tid: u64, stats: *mut ThreadStats) -> i64 {
+    syscall2(SYSNO_THREAD_STATS as u64, tid, stats as u64) as i64
+}
+
+// Heap um 'increment' Bytes vergroessern (oder verkleinern)
+// Rueckgabe: bisheriges Ende des Heaps, oder -ENOMEM falls der Bereich ueberschritten wird
+#[link_section = ".user_text"]
+pub fn usr_sbrk(increment: i64) -> i64 {
+    syscall1(SYSNO_SBRK as u64, increment as u64) as i64
+}
+
+// Anonymen Bereich einblenden, 'addr' wird nur mit MAP_FIXED beachtet
+// Rueckgabe: Startadresse des Bereichs, -EINVAL bei ungueltigen Argumenten, oder -ENOMEM
+#[link_section = ".user_text"]
+pub fn usr_mmap(addr: u64, len: u64, prot: u64, flags: u64) -> i64 {
+    syscall4(SYSNO_MMAP as u64, addr, len, prot, flags) as i64
+}
+
+// Rueckgabe: 0, oder -EINVAL falls der Bereich nicht aus 'usr_mmap' stammt
+#[link_section = ".user_text"]
+pub fn usr_munmap(addr: u64, len: u64) -> i64 {
+    syscall2(SYSNO_MUNMAP as u64, addr, len) as i64
+}
+
+// Rueckgabe: 0, -EINVAL bei ungueltigen Rechten, oder -ENOMEM falls der Bereich
+// nicht vollstaendig eingeblendet ist
+#[link_section = ".user_text"]
+pub fn usr_mprotect(addr: u64, len: u64, prot: u64) -> i64 {
+    syscall3(SYSNO_MPROTECT as u64, addr, len, prot) as i64
+}
+
+// Prozess kopieren (Copy-on-Write), das Kind laeuft nur mit dem rufenden Thread weiter
+// Rueckgabe: pid des Kindes im Elternteil, 0 im Kind
+#[link_section = ".user_text"]
+pub fn usr_fork() -> i64 {
+    syscall0(SYSNO_FORK as u64) as i64
+}
+
+// Shared-Memory-Objekt mit 'size' Bytes anlegen; mit 'key' != 0 wird ein
+// vorhandenes Objekt mit demselben Schluessel geliefert
+// Rueckgabe: id des Objekts, oder -EINVAL/-ENOMEM; nur Objekte, die der Prozess
+// selbst angelegt bzw. nachgeschlagen hat, koennen eingeblendet werden
+#[link_section = ".user_text"]
+pub fn usr_shm_create(key: u64, size: u64) -> i64 {
+    syscall2(SYSNO_SHM_CREATE as u64, key, size) as i64
+}
+
+// Objekt 'id' einblenden, bei 'addr' = 0 waehlt der Kernel die Adresse
+// Rueckgabe: Startadresse, oder -EINVAL
+#[link_section = ".user_text"]
+pub fn usr_shm_map(id: u64, addr: u64, prot: u64) -> i64 {
+    syscall3(SYSNO_SHM_MAP as u64, id, addr, prot) as i64
+}
+
+// Mit 'usr_shm_map' ab 'addr' eingeblendetes Objekt entfernen
+// Rueckgabe: 0, oder -EINVAL
+#[link_section = ".user_text"]
+pub fn usr_shm_unmap(addr: u64) -> i64 {
+    syscall1(SYSNO_SHM_UNMAP as u64, addr) as i64
+}
+
+// Speicherstatistik (Pools, Eigentuemer, eigener Prozess) nach 'stats' kopieren
+// Rueckgabe: 0, oder -EFAULT
+#[link_section = ".user_text"]
+pub fn usr_mem_stats(stats: *mut MemStats) -> i64 {
+    syscall1(SYSNO_MEM_STATS as u64, stats as u64) as i64
 }
 
-pub fn usr_write(buff: *const u8, len: u64) {
-    syscall2(SYSNO_WRITE as u64, buff as u64, len);
+// Statische Prioritaet des aufrufenden Threads setzen (1 .. 7, nur fuer 'sched=prio')
+// Rueckgabe: 0, oder -EINVAL
+#[link_section = ".user_text"]
+pub fn usr_set_priority(priority: u64) -> i64 {
+    syscall1(SYSNO_SET_PRIORITY as u64, priority) as i64
 }
 
 /* 
@@ -60,6 +201,8 @@ pub fn usr_write(buff: *const u8, len: u64) {
 
 
 
+// Der Syscall-Handler sichert nur die Register, die laut ABI erhalten bleiben
+// muessen (siehe 'syscalls.asm'), alle anderen gelten daher als veraendert
 #[inline(always)]
 #[allow(unused_mut)]
 pub fn syscall0(arg0: u64) -> u64 {
@@ -67,6 +210,7 @@ pub fn syscall0(arg0: u64) -> u64 {
     unsafe {
         asm!("int 0x80",
             inlateout("rax") arg0 => ret,
+            clobber_abi("C"),
             options(preserves_flags, nostack)
         );
     }
@@ -91,6 +235,7 @@ pub fn syscall1(arg0: u64, arg1: u64) -> u64 {
             "int 0x80",
             inlateout("rax") arg0 => ret,
             in("rdi") arg1,
+            clobber_abi("C"),
             options(preserves_flags, nostack)
         );
     }
@@ -107,8 +252,48 @@ pub fn syscall2(arg0: u64, arg1: u64, arg2: u64) -> u64 {
             inlateout("rax") arg0 => ret,
             in("rdi") arg1,
             in("rsi") arg2,
+            clobber_abi("C"),
             options(preserves_flags, nostack)
         );
     }
     ret
-}
\ No newline at end of file
+}
+
+#[inline(always)]
+#[allow(unused_mut)]
+pub fn syscall3(arg0: u64, arg1: u64, arg2: u64, arg3: u64) -> u64 {
+    let mut ret: u64;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inlateout("rax") arg0 => ret,
+            in("rdi") arg1,
+            in("rsi") arg2,
+            in("rdx") arg3,
+            clobber_abi("C"),
+            options(preserves_flags, nostack)
+        );
+    }
+    ret
+}
+
+// Der 4. Parameter wird in r10 uebergeben, da 'rcx' im Syscall-Handler fuer
+// DS und ES genutzt wird (siehe 'syscalls.asm')
+#[inline(always)]
+#[allow(unused_mut)]
+pub fn syscall4(arg0: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64) -> u64 {
+    let mut ret: u64;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inlateout("rax") arg0 => ret,
+            in("rdi") arg1,
+            in("rsi") arg2,
+            in("rdx") arg3,
+            in("r10") arg4,
+            clobber_abi("C"),
+            options(preserves_flags, nostack)
+        );
+    }
+    ret
+}
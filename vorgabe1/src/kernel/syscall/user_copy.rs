@@ -0,0 +1,187 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: user_copy                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Access to user buffers from system calls. Pointers passed by a  ║
+   ║         user thread are never dereferenced directly, the kernel copies  ║
+   ║         the data with 'copy_from_user' / 'copy_to_user' (or 'UserPtr'). ║
+   ║                                                                         ║
+   ║         The range is first checked against the mappings of the calling  ║
+   ║         process (only '.user_text' is readable in the kernel part),     ║
+   ║         system calls may also check a whole buffer with 'access_ok'.    ║
+   ║         The copy itself runs in '_user_copy' with the AC flag set       ║
+   ║         ('stac'/'clac'), otherwise SMAP would stop the access. Lazily   ║
+   ║         backed pages are mapped by the page-fault handler as usual. If  ║
+   ║         a fault cannot be resolved (e.g. another thread has removed the ║
+   ║         area meanwhile), 'page_fault' continues at '_user_copy_fixup'   ║
+   ║         and the copy returns an error instead of a kernel panic.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::arch::global_asm;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::consts::KERNEL_VM_SIZE;
+use crate::kernel::paging::pages;
+use crate::kernel::threads::process::VmProt;
+use crate::kernel::threads::scheduler;
+
+// Warum ein Zugriff auf einen User-Puffer nicht moeglich war
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UserCopyError {
+    NoProcess,  // aufrufender Thread gehoert zu keinem Prozess
+    BadAddress, // Bereich nicht (vollstaendig) eingeblendet oder Zugriffsart nicht erlaubt
+    Fault,      // Page-Fault waehrend des Kopierens, der nicht aufgeloest werden konnte
+}
+
+// _user_copy(dst, src, len, smap) -> Anzahl nicht kopierter Bytes
+// 'rep movsb' ist unterbrechbar: bei einem Page-Fault steht in rcx die Anzahl der
+// restlichen Bytes, der Fixup setzt direkt hinter dem Befehl fort.
+global_asm!(
+    ".section .text",
+    ".global _user_copy",
+    ".global _user_copy_insn",
+    ".global _user_copy_fixup",
+    "_user_copy:",
+    "    mov r8, rcx",
+    "    mov rcx, rdx",
+    "    test r8, r8",
+    "    jz 2f",
+    "    stac",
+    "2:",
+    "_user_copy_insn:",
+    "    rep movsb",
+    "_user_copy_fixup:",
+    "    test r8, r8",
+    "    jz 3f",
+    "    clac",
+    "3:",
+    "    mov rax, rcx",
+    "    ret",
+);
+
+extern "C" {
+    fn _user_copy(dst: *mut u8, src: *const u8, len: usize, smap: u64) -> usize;
+    static _user_copy_insn: u8;
+    static _user_copy_fixup: u8;
+}
+
+/**
+    Description: Check whether the running thread may access `[addr, addr + len)` \
+                 with `prot`, e.g. a whole user buffer before it is copied piecewise.
+
+    Return: \
+           `Ok` or `BadAddress` if the range is not completely mapped in user memory
+*/
+pub fn access_ok(addr: usize, len: usize, prot: VmProt) -> Result<(), UserCopyError> {
+    // Gemeinsame Sektion '.user_text' im Kernel-Bereich, nur lesbar
+    if prot == VmProt::READ && pages::pg_is_user_section(addr, len) {
+        return Ok(());
+    }
+    if addr < KERNEL_VM_SIZE {
+        return Err(UserCopyError::BadAddress);
+    }
+
+    let process = scheduler::get_active_process().ok_or(UserCopyError::NoProcess)?;
+    let ok = process.lock().is_accessible(addr, len, prot);
+    if ok {
+        Ok(())
+    } else {
+        Err(UserCopyError::BadAddress)
+    }
+}
+
+// Kopieren mit gesetztem AC-Flag, der Prozess darf dabei nicht gesperrt sein,
+// da 'page_fault' ihn fuer das Einblenden von Seiten benoetigt
+fn raw_copy(dst: *mut u8, src: *const u8, len: usize) -> Result<(), UserCopyError> {
+    let left = unsafe { _user_copy(dst, src, len, pages::pg_smap_enabled() as u64) };
+    if left == 0 {
+        Ok(())
+    } else {
+        Err(UserCopyError::Fault)
+    }
+}
+
+/**
+    Description: Copy `dst.len()` bytes from the user buffer at `src` into `dst`.
+
+    Return: \
+           `Ok` or the reason why the user buffer could not be read
+*/
+pub fn copy_from_user(dst: &mut [u8], src: usize) -> Result<(), UserCopyError> {
+    access_ok(src, dst.len(), VmProt::READ)?;
+    raw_copy(dst.as_mut_ptr(), src as *const u8, dst.len())
+}
+
+/**
+    Description: Copy `src` into the user buffer at `dst`.
+
+    Return: \
+           `Ok` or the reason why the user buffer could not be written
+*/
+pub fn copy_to_user(dst: usize, src: &[u8]) -> Result<(), UserCopyError> {
+    access_ok(dst, src.len(), VmProt::WRITE)?;
+    raw_copy(dst as *mut u8, src.as_ptr(), src.len())
+}
+
+/**
+    Description: Called by `page_fault` for a fault in ring 0 which could not be resolved.
+
+    Return: \
+           address to continue at, if the fault happened while copying a user buffer
+*/
+pub fn fixup(rip: u64) -> Option<u64> {
+    let (insn, fixup) = unsafe {
+        (
+            ptr::addr_of!(_user_copy_insn) as u64,
+            ptr::addr_of!(_user_copy_fixup) as u64,
+        )
+    };
+    if rip == insn {
+        Some(fixup)
+    } else {
+        None
+    }
+}
+
+/**
+    Description: Typed pointer into the user address space, e.g. a syscall argument. \
+                 It is never dereferenced, `read` and `write` copy the value. \
+                 `T` must be valid for any bit pattern (integers, `repr(C)` structs).
+*/
+#[derive(Clone, Copy)]
+pub struct UserPtr<T> {
+    addr: usize,
+    _type: PhantomData<*mut T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: u64) -> Self {
+        UserPtr {
+            addr: addr as usize,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    // Wert aus dem User-Puffer lesen
+    pub fn read(&self) -> Result<T, UserCopyError> {
+        access_ok(self.addr, size_of::<T>(), VmProt::READ)?;
+        let mut value = MaybeUninit::<T>::uninit();
+        raw_copy(value.as_mut_ptr() as *mut u8, self.addr as *const u8, size_of::<T>())?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    // Wert in den User-Puffer schreiben
+    pub fn write(&self, value: &T) -> Result<(), UserCopyError> {
+        access_ok(self.addr, size_of::<T>(), VmProt::WRITE)?;
+        raw_copy(self.addr as *mut u8, value as *const T as *const u8, size_of::<T>())
+    }
+}
@@ -0,0 +1,60 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: condvar                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Condition variable used together with a 'KMutex'. 'wait'        ║
+   ║         releases the mutex and blocks the calling thread atomically     ║
+   ║         (interrupts are disabled in between), so a 'notify_*' cannot be ║
+   ║         lost. As usual the condition must be re-checked after 'wait'.   ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use crate::kernel::cpu;
+use crate::kernel::threads::kmutex::KMutexGuard;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::wait_queue::WaitQueue;
+
+pub struct CondVar {
+    waiters: WaitQueue,
+}
+
+impl CondVar {
+    pub const fn new() -> Self {
+        CondVar {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /**
+        Description: Release the mutex of `guard`, block until notified and
+                     acquire the mutex again.
+
+        Parameters: \
+               `guard` guard of the locked mutex
+
+        Return: \
+               new guard of the mutex
+    */
+    pub fn wait<'a, T>(&self, guard: KMutexGuard<'a, T>) -> KMutexGuard<'a, T> {
+        let mutex = guard.mutex();
+
+        let irq = cpu::disable_int_nested();
+        drop(guard);
+        Scheduler::block(&self.waiters);
+        cpu::enable_int_nested(irq);
+
+        mutex.lock()
+    }
+
+    /**
+        Description: Wake one thread waiting in `wait`. May be called from an ISR.
+    */
+    pub fn notify_one(&self) -> bool {
+        Scheduler::wake_one(&self.waiters)
+    }
+
+    /**
+        Description: Wake all threads waiting in `wait`. May be called from an ISR.
+    */
+    pub fn notify_all(&self) -> usize {
+        Scheduler::wake_all(&self.waiters)
+    }
+}
@@ -1,16 +1,26 @@
+use crate::kernel::cpu;
 use crate::kernel::threads::scheduler;
 
 pub extern "C" fn idle_thread_entry() {
     scheduler::set_initialized();
     loop {
-        print!("I");
+        // Ressourcen beendeter Threads freigeben
+        scheduler::Scheduler::reap();
 
-        let mut x: u64 = 0;
-        loop {
-            x = x + 1;
-            if x > 100000000 {
-                break;
-            }
+        // Andere bereite Threads zuerst (die Strategie verdraengt den
+        // Idle-Thread nicht unbedingt sofort)
+        scheduler::Scheduler::yield_cpu();
+
+        // Bis zur naechsten Unterbrechung schlafen, statt zu spinnen.
+        // Der Idle-Thread darf nie blockieren, er laeuft immer dann,
+        // wenn kein anderer Thread bereit ist. Bis zum 'hlt' bleiben die
+        // Interrupts gesperrt ('sti; hlt' ist atomar), damit kein Wecken
+        // zwischen Pruefen und Anhalten verloren geht.
+        cpu::disable_int();
+        if scheduler::Scheduler::has_ready_threads() {
+            cpu::enable_int();
+        } else {
+            cpu::wait_for_int();
         }
     }
 }
@@ -0,0 +1,168 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: kmutex                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Blocking mutex for threads. In contrast to 'spin::Mutex' a      ║
+   ║         thread waiting for the lock is parked in a 'WaitQueue' and does ║
+   ║         not burn its time slice. The owner is tracked by its tid; a     ║
+   ║         thread locking a mutex it already holds causes a panic instead  ║
+   ║         of a deadlock.                                                  ║
+   ║                                                                         ║
+   ║         The internal state is only accessed with interrupts disabled,   ║
+   ║         so holding a 'KMutex' never blocks the timer interrupt. Must    ║
+   ║         not be locked from an ISR.                                      ║
+   ║                                                                         ║
+   ║         Used for processes: with a 'spin::Mutex' a thread preempted by  ║
+   ║         the PIT while holding the lock would make the page-fault        ║
+   ║         handler (interrupts disabled) of another thread spin forever.   ║
+   ║         With 'KMutex' the faulting thread blocks until the owner has    ║
+   ║         run again, and a fault of the owner itself is detected via      ║
+   ║         'owner' instead of spinning (see 'page_fault').                 ║
+   ║                                                                         ║
+   ║         Before the first thread runs, BOOT_TID is used as owner.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use spin::Mutex;
+
+use crate::kernel::cpu;
+use crate::kernel::threads::scheduler;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::wait_queue::WaitQueue;
+
+// Besitzer waehrend des Bootens (noch kein Thread aktiv)
+pub const BOOT_TID: usize = usize::MAX;
+
+// Nur mit gesperrten Interrupts aufrufen
+fn current_tid() -> usize {
+    scheduler::try_get_active_tid().unwrap_or(BOOT_TID)
+}
+
+pub struct KMutex<T> {
+    owner: Mutex<Option<usize>>, // tid des Besitzers
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for KMutex<T> {}
+unsafe impl<T: Send> Sync for KMutex<T> {}
+
+pub struct KMutexGuard<'a, T> {
+    mutex: &'a KMutex<T>,
+}
+
+impl<T> KMutex<T> {
+    pub const fn new(data: T) -> Self {
+        KMutex {
+            owner: Mutex::new(None),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /**
+        Description: Acquire the mutex. Blocks the calling thread while the
+                     mutex is held by another thread.
+
+        Return: \
+               guard, the mutex is released when it is dropped
+    */
+    pub fn lock(&self) -> KMutexGuard<'_, T> {
+        loop {
+            let irq = cpu::disable_int_nested();
+            if self.try_acquire() {
+                cpu::enable_int_nested(irq);
+                return KMutexGuard { mutex: self };
+            }
+
+            // Interrupts bleiben bis 'block' aus, sonst koennte 'unlock'
+            // dazwischen kommen und der Thread wuerde nie geweckt
+            Scheduler::block(&self.waiters);
+            cpu::enable_int_nested(irq);
+        }
+    }
+
+    /**
+        Description: Acquire the mutex without blocking.
+
+        Return: \
+               `Some(guard)` or `None` if the mutex is held by another thread
+    */
+    pub fn try_lock(&self) -> Option<KMutexGuard<'_, T>> {
+        let irq = cpu::disable_int_nested();
+        let acquired = self.try_acquire();
+        cpu::enable_int_nested(irq);
+
+        if acquired {
+            Some(KMutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    /**
+        Description: Return tid of the thread holding the mutex or `None`.
+    */
+    pub fn owner(&self) -> Option<usize> {
+        let irq = cpu::disable_int_nested();
+        let owner = *self.owner.lock();
+        cpu::enable_int_nested(irq);
+        owner
+    }
+
+    // Nur mit gesperrten Interrupts aufrufen
+    fn try_acquire(&self) -> bool {
+        let tid = current_tid();
+        let mut owner = self.owner.lock();
+        match *owner {
+            None => {
+                *owner = Some(tid);
+                true
+            }
+            Some(t) if t == tid => panic!("KMutex: thread {} locks a mutex it already holds", tid),
+            Some(_) => false,
+        }
+    }
+
+    // Freigeben und einen wartenden Thread wecken (durch Drop des Guards)
+    fn unlock(&self) {
+        let irq = cpu::disable_int_nested();
+        let tid = current_tid();
+        {
+            let mut owner = self.owner.lock();
+            if *owner != Some(tid) {
+                panic!("KMutex: thread {} unlocks a mutex owned by {:?}", tid, *owner);
+            }
+            *owner = None;
+        }
+        Scheduler::wake_one(&self.waiters);
+        cpu::enable_int_nested(irq);
+    }
+}
+
+impl<'a, T> KMutexGuard<'a, T> {
+    // Mutex, zu dem der Guard gehoert (fuer 'CondVar::wait')
+    pub(super) fn mutex(&self) -> &'a KMutex<T> {
+        self.mutex
+    }
+}
+
+impl<T> Deref for KMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for KMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for KMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
@@ -1,4 +1,10 @@
+pub mod condvar;
 pub mod idle_thread;
+pub mod kmutex;
+pub mod policy;
+pub mod process;
 pub mod scheduler;
+pub mod semaphore;
 pub mod stack;
 pub mod thread;
+pub mod wait_queue;
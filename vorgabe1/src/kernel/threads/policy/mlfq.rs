@@ -0,0 +1,123 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: mlfq                                                            ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Multi-level feedback queue. New threads start in level 0 (the   ║
+   ║         highest). A thread using up the time slice of its level is      ║
+   ║         demoted, a thread waking up (e.g. from the keyboard) is boosted ║
+   ║         back to level 0. The time slice starts with the quantum of the  ║
+   ║         thread and doubles with each level.                             ║
+   ║         To avoid starvation all threads are boosted periodically.       ║
+   ║                                                                         ║
+   ║         The idle thread always stays in the lowest level.               ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::kernel::threads::policy::{ReadyReason, SchedPolicy};
+use crate::kernel::threads::thread;
+use crate::mylib::queue;
+
+// Anzahl der Ebenen
+const MLFQ_LEVELS: usize = 4;
+
+// Alle 100 Ticks (1s) werden alle Threads auf Ebene 0 angehoben
+const BOOST_INTERVAL: usize = 100;
+
+pub struct Mlfq {
+    queues: Vec<queue::Queue<Box<thread::Thread>>>, // Index = Ebene
+    ticks: usize,                                   // Ticks seit dem letzten Boost
+}
+
+impl Mlfq {
+    pub fn new() -> Self {
+        let mut queues = Vec::with_capacity(MLFQ_LEVELS);
+        for _ in 0..MLFQ_LEVELS {
+            queues.push(queue::Queue::new());
+        }
+        Mlfq {
+            queues,
+            ticks: 0,
+        }
+    }
+
+    // Zeitscheibe auf Ebene 'level', verdoppelt sich je Ebene
+    fn time_slice(current: &thread::Thread, level: usize) -> usize {
+        current.get_quantum() << level
+    }
+
+    // hoechste Ebene (kleinster Index) mit bereitem Thread
+    fn highest_ready(&self) -> Option<usize> {
+        self.queues.iter().position(|q| !q.is_empty())
+    }
+
+    // alle bereiten Threads auf Ebene 0 anheben
+    fn boost(&mut self) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(that) = self.queues[level].dequeue() {
+                self.enqueue(that, ReadyReason::Woken);
+            }
+        }
+    }
+}
+
+impl SchedPolicy for Mlfq {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn enqueue(&mut self, mut that: Box<thread::Thread>, reason: ReadyReason) {
+        if that.get_priority() == thread::IDLE_PRIORITY {
+            that.set_level(MLFQ_LEVELS - 1);
+        } else {
+            match reason {
+                ReadyReason::New | ReadyReason::Woken => that.set_level(0),
+                ReadyReason::Preempted | ReadyReason::Yielded => {}
+            }
+        }
+        let level = that.get_level().min(MLFQ_LEVELS - 1);
+        self.queues[level].enqueue(that);
+    }
+
+    fn dequeue(&mut self) -> Option<Box<thread::Thread>> {
+        let level = self.highest_ready()?;
+        self.queues[level].dequeue()
+    }
+
+    fn contains(&self, tid: usize) -> bool {
+        self.queues
+            .iter()
+            .any(|q| q.contains(|t| thread::Thread::get_tid(t.as_ref()) == tid))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.highest_ready().is_none()
+    }
+
+    fn tick(&mut self, current: &mut thread::Thread) -> bool {
+        self.ticks += 1;
+        if self.ticks >= BOOST_INTERVAL {
+            self.ticks = 0;
+            self.boost();
+            if current.get_priority() != thread::IDLE_PRIORITY {
+                current.set_level(0);
+            }
+        }
+
+        // Zeitscheibe aufgebraucht -> eine Ebene tiefer
+        let level = current.get_level();
+        let expired = current.get_slice_used() >= Mlfq::time_slice(current, level);
+        if expired && current.get_priority() != thread::IDLE_PRIORITY {
+            // Behaelt der Thread die CPU, bekommt er die volle Zeitscheibe der neuen Ebene
+            current.set_level((level + 1).min(MLFQ_LEVELS - 1));
+            current.start_slice();
+        }
+
+        match self.highest_ready() {
+            None => false,
+            Some(ready) if expired => ready <= current.get_level(),
+            // Thread auf hoeherer Ebene bereit (z.B. gerade aufgewacht)
+            Some(ready) => ready < level,
+        }
+    }
+}
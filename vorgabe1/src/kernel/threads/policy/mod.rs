@@ -0,0 +1,85 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: policy                                                          ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Pluggable scheduling policies. A policy owns all ready threads  ║
+   ║         and decides which one runs next and when the running thread is  ║
+   ║         preempted. The scheduler itself only handles blocking, sleeping ║
+   ║         and the thread switch.                                          ║
+   ║                                                                         ║
+   ║         The policy is selected at boot using the kernel command line,   ║
+   ║         e.g. 'sched=mlfq' (see 'boot/grub.cfg').                        ║
+   ║                                                                         ║
+   ║         All functions are called with the scheduler locked and          ║
+   ║         interrupts disabled.                                            ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+pub mod mlfq;
+pub mod round_robin;
+pub mod static_priority;
+
+use alloc::boxed::Box;
+
+use crate::kernel::threads::thread;
+
+// Warum ein Thread (wieder) in die Ready-Queue kommt
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReadyReason {
+    New,       // neu erzeugt
+    Preempted, // durch den PIT verdraengt
+    Yielded,   // CPU freiwillig abgegeben
+    Woken,     // war blockiert oder hat geschlafen
+}
+
+pub trait SchedPolicy {
+    // Name fuer Ausgaben
+    fn name(&self) -> &'static str;
+
+    // Thread in die Ready-Queue(s) eintragen
+    fn enqueue(&mut self, that: Box<thread::Thread>, reason: ReadyReason);
+
+    // Naechsten Thread auswaehlen und aushaengen
+    fn dequeue(&mut self) -> Option<Box<thread::Thread>>;
+
+    // Ist der Thread 'tid' bereit?
+    fn contains(&self, tid: usize) -> bool;
+
+    // Ist kein Thread bereit?
+    fn is_empty(&self) -> bool;
+
+    // Wird vom PIT bei jedem Tick mit dem laufenden Thread aufgerufen.
+    // Rueckgabe 'true', falls dieser verdraengt werden soll.
+    fn tick(&mut self, current: &mut thread::Thread) -> bool;
+}
+
+// Verfuegbare Strategien, Auswahl ueber die Kommandozeile
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PolicyKind {
+    RoundRobin,
+    StaticPriority,
+    Mlfq,
+}
+
+impl PolicyKind {
+    /**
+        Description: Map the value of `sched=` on the kernel command line to a policy.
+
+        Parameters: \
+               `name` one of "rr", "prio", "mlfq"
+    */
+    pub fn from_name(name: &str) -> Option<PolicyKind> {
+        match name {
+            "rr" => Some(PolicyKind::RoundRobin),
+            "prio" => Some(PolicyKind::StaticPriority),
+            "mlfq" => Some(PolicyKind::Mlfq),
+            _ => None,
+        }
+    }
+
+    pub fn create(self) -> Box<dyn SchedPolicy> {
+        match self {
+            PolicyKind::RoundRobin => Box::new(round_robin::RoundRobin::new()),
+            PolicyKind::StaticPriority => Box::new(static_priority::StaticPriority::new()),
+            PolicyKind::Mlfq => Box::new(mlfq::Mlfq::new()),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: round_robin                                                     ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Round robin without priorities. The running thread is           ║
+   ║         preempted after its quantum if another thread is ready.         ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+
+use crate::kernel::threads::policy::{ReadyReason, SchedPolicy};
+use crate::kernel::threads::thread;
+use crate::mylib::queue;
+
+pub struct RoundRobin {
+    ready_queue: queue::Queue<Box<thread::Thread>>,
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        RoundRobin {
+            ready_queue: queue::Queue::new(),
+        }
+    }
+}
+
+impl SchedPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round robin"
+    }
+
+    fn enqueue(&mut self, that: Box<thread::Thread>, _reason: ReadyReason) {
+        self.ready_queue.enqueue(that);
+    }
+
+    fn dequeue(&mut self) -> Option<Box<thread::Thread>> {
+        self.ready_queue.dequeue()
+    }
+
+    fn contains(&self, tid: usize) -> bool {
+        self.ready_queue
+            .contains(|t| thread::Thread::get_tid(t.as_ref()) == tid)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+
+    fn tick(&mut self, current: &mut thread::Thread) -> bool {
+        current.quantum_expired() && !self.ready_queue.is_empty()
+    }
+}
@@ -0,0 +1,70 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: static_priority                                                 ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Static priorities, one ready queue per priority. The thread     ║
+   ║         with the highest priority runs; threads of equal priority are   ║
+   ║         scheduled round robin (after their quantum). A thread with a    ║
+   ║         higher priority preempts immediately. Lower priorities can      ║
+   ║         starve. A thread sets its own priority with 'usr_set_priority'. ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::kernel::threads::policy::{ReadyReason, SchedPolicy};
+use crate::kernel::threads::thread;
+use crate::mylib::queue;
+
+pub struct StaticPriority {
+    queues: Vec<queue::Queue<Box<thread::Thread>>>, // Index = Prioritaet
+}
+
+impl StaticPriority {
+    pub fn new() -> Self {
+        let mut queues = Vec::with_capacity(thread::PRIO_LEVELS);
+        for _ in 0..thread::PRIO_LEVELS {
+            queues.push(queue::Queue::new());
+        }
+        StaticPriority { queues }
+    }
+
+    // hoechste Prioritaet mit bereitem Thread
+    fn highest_ready(&self) -> Option<usize> {
+        self.queues.iter().rposition(|q| !q.is_empty())
+    }
+}
+
+impl SchedPolicy for StaticPriority {
+    fn name(&self) -> &'static str {
+        "static priority"
+    }
+
+    fn enqueue(&mut self, that: Box<thread::Thread>, _reason: ReadyReason) {
+        let prio = that.get_priority();
+        self.queues[prio].enqueue(that);
+    }
+
+    fn dequeue(&mut self) -> Option<Box<thread::Thread>> {
+        let prio = self.highest_ready()?;
+        self.queues[prio].dequeue()
+    }
+
+    fn contains(&self, tid: usize) -> bool {
+        self.queues
+            .iter()
+            .any(|q| q.contains(|t| thread::Thread::get_tid(t.as_ref()) == tid))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.highest_ready().is_none()
+    }
+
+    fn tick(&mut self, current: &mut thread::Thread) -> bool {
+        match self.highest_ready() {
+            Some(prio) if prio > current.get_priority() => true,
+            Some(prio) if prio == current.get_priority() => current.quantum_expired(),
+            Some(_) => false,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,649 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: process                                                         ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: A process owns an address space (PML4), the list of its user    ║
+   ║         mappings and its threads. All threads of a process share the    ║
+   ║         address space, each thread gets its own user stack in a slot    ║
+   ║         below USER_STACK_VM_START (see 'USER_STACK_STRIDE') with a      ║
+   ║         guard page beneath it. Stacks and the heap ('sbrk') are backed  ║
+   ║         lazily by the page-fault handler.                               ║
+   ║                                                                         ║
+   ║         Further anonymous areas are created with 'mmap' and changed     ║
+   ║         with 'munmap' and 'mprotect'. Areas are split if only a part of ║
+   ║         them is unmapped or protected. On request 'mmap' backs an area  ║
+   ║         with 2 MB pages, such areas can only be split at 2 MB borders.  ║
+   ║         Shared memory objects ('shm') are mapped as a whole, only if    ║
+   ║         the process holds a handle on them (from 'shm::create').        ║
+   ║                                                                         ║
+   ║         'fork' copies a process: the page frames are shared and marked  ║
+   ║         copy-on-write, a write fault copies them (see 'page_fault').    ║
+   ║                                                                         ║
+   ║         Threads hold a reference ('Arc') to their process. When the     ║
+   ║         last thread has been released, the page tables are freed. The   ║
+   ║         process is protected by a 'KMutex', a thread preempted while    ║
+   ║         holding it keeps it and other threads block until it is free.   ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::consts;
+#[cfg(feature = "debug_heap")]
+use crate::kernel::allocator;
+#[cfg(feature = "debug_heap")]
+use crate::kernel::paging::frames;
+use crate::kernel::paging::frames::PhysAddr;
+use crate::kernel::paging::pages;
+use crate::kernel::paging::pages::PageSize;
+use crate::kernel::shm;
+use crate::kernel::threads::kmutex::KMutex;
+
+static PROCESS_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Wofuer ein Bereich im User-Adressraum genutzt wird
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VmKind {
+    Stack { tid: usize },      // User-Stack des Threads 'tid'
+    StackGuard { tid: usize }, // nie gemappte Seite unter dem Stack von 'tid'
+    Image,                     // Segment eines geladenen ELF-Programms
+    Heap,                      // waechst mit 'sbrk'
+    Mmap,                      // anonymer Bereich aus 'mmap'
+    Shm { id: usize },         // eingeblendetes Shared-Memory-Objekt (siehe 'shm')
+}
+
+// Zugriffsrechte eines Bereichs (Werte wie PROT_* in 'user_api')
+bitflags::bitflags! {
+    pub struct VmProt: u64 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+// Wann die Page-Frames eines Bereichs alloziert werden
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backing {
+    Eager, // beim Anlegen des Bereichs
+    Lazy,  // beim ersten Zugriff durch den Page-Fault-Handler (genullt)
+}
+
+// Ein Bereich im User-Adressraum eines Prozesses
+#[derive(Clone, Copy, Debug)]
+pub struct VmArea {
+    pub start: usize, // virtuelle Startadresse (4 KB aligniert)
+    pub len: usize,   // Laenge in Bytes (Vielfaches von 4 KB)
+    pub kind: VmKind,
+    pub prot: VmProt,
+    pub backing: Backing,
+    pub page_size: PageSize, // 2 MB nur fuer 'mmap' mit MAP_HUGETLB
+}
+
+impl VmArea {
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    pub fn contains(&self, vm_addr: usize) -> bool {
+        vm_addr >= self.start && vm_addr < self.end()
+    }
+
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        start < self.end() && end > self.start
+    }
+}
+
+pub struct Process {
+    pid: usize,
+    pml4_addr: PhysAddr,     // gemeinsamer Adressraum aller Threads
+    mappings: Vec<VmArea>,   // Bereiche im User-Adressraum
+    threads: Vec<usize>,     // tids der Threads
+    stack_slots: Vec<usize>, // belegte Stack-Slots (Index unter USER_STACK_VM_START)
+    heap_start: usize,       // Anfang des Heaps (4 KB aligniert)
+    brk: usize,              // aktuelles Ende des Heaps (exklusiv)
+    shm_handles: Vec<usize>, // ids der Shared-Memory-Objekte, auf die der Prozess eine Referenz haelt
+}
+
+impl Process {
+    /**
+        Description: Create a new process with its own address space.
+
+        Return: \
+               process shared by all of its threads
+    */
+    pub fn new() -> Arc<KMutex<Process>> {
+        let pid = PROCESS_ID_COUNTER.fetch_add(1, Ordering::SeqCst);
+
+        // Eigene PML4 anlegen, die Kernel-Tabellen werden geteilt
+        let pml4_addr = pages::pg_create_address_space();
+        kprintln!("Process::new, pid={}, pml4_addr={:?}", pid, pml4_addr);
+
+        Arc::new(KMutex::new(Process {
+            pid,
+            pml4_addr,
+            mappings: Vec::new(),
+            threads: Vec::new(),
+            stack_slots: Vec::new(),
+            heap_start: consts::USER_HEAP_VM_START,
+            brk: consts::USER_HEAP_VM_START,
+            shm_handles: Vec::new(),
+        }))
+    }
+
+    pub fn get_pid(&self) -> usize {
+        self.pid
+    }
+
+    pub fn get_pml4_addr(&self) -> PhysAddr {
+        self.pml4_addr
+    }
+
+    // Eingeblendete Page-Frames und Page-Tables des User-Bereichs (fuer 'MemStats')
+    pub fn count_frames(&self) -> (usize, usize) {
+        pages::pg_count_user_frames(self.pml4_addr)
+    }
+
+    pub fn get_threads(&self) -> &[usize] {
+        &self.threads
+    }
+
+    pub fn get_mappings(&self) -> &[VmArea] {
+        &self.mappings
+    }
+
+    // Bereich eintragen, die Seiten muessen bereits gemappt sein
+    pub fn add_mapping(&mut self, area: VmArea) {
+        self.mappings.push(area);
+    }
+
+    // Bereich suchen, der 'vm_addr' enthaelt
+    pub fn find_mapping(&self, vm_addr: usize) -> Option<&VmArea> {
+        self.mappings.iter().find(|m| m.contains(vm_addr))
+    }
+
+    pub fn get_brk(&self) -> usize {
+        self.brk
+    }
+
+    // Heap direkt hinter 'vm_addr' beginnen lassen (z.B. Ende des Programms),
+    // nur moeglich, solange der Heap noch leer ist
+    pub fn set_heap_start(&mut self, vm_addr: usize) {
+        assert!(self.brk == self.heap_start, "set_heap_start: Heap wird bereits genutzt!");
+        let start = vm_addr.next_multiple_of(consts::PAGE_SIZE);
+        self.heap_start = start;
+        self.brk = start;
+    }
+
+    /**
+        Description: Move the end of the heap by `increment` bytes. New pages are \
+                     backed on first touch, released pages are unmapped.
+
+        Parameters: \
+               `increment` bytes to grow (> 0) or shrink (< 0) the heap
+
+        Return: \
+               previous end of the heap or `None` if the new end is out of range
+    */
+    pub fn sbrk(&mut self, increment: isize) -> Option<usize> {
+        let old_brk = self.brk;
+        let new_brk = old_brk.checked_add_signed(increment)?;
+        if new_brk < self.heap_start || new_brk > consts::USER_STACKS_VM_BOTTOM {
+            return None;
+        }
+
+        let old_end = old_brk.next_multiple_of(consts::PAGE_SIZE);
+        let new_end = new_brk.next_multiple_of(consts::PAGE_SIZE);
+        if new_end > consts::USER_MMAP_VM_START || self.is_used(old_end, new_end) {
+            return None;
+        }
+        if new_end < old_end {
+            pages::pg_unmap_user_range(self.pml4_addr, new_end, (old_end - new_end) / consts::PAGE_SIZE);
+        }
+
+        // Bereich des Heaps anpassen
+        self.mappings.retain(|m| m.kind != VmKind::Heap);
+        if new_end > self.heap_start {
+            self.mappings.push(VmArea {
+                start: self.heap_start,
+                len: new_end - self.heap_start,
+                kind: VmKind::Heap,
+                prot: VmProt::READ | VmProt::WRITE,
+                backing: Backing::Lazy,
+                page_size: PageSize::Size4K,
+            });
+        }
+
+        self.brk = new_brk;
+        Some(old_brk)
+    }
+
+    // Ueberschneidet sich [start, end) mit einem Bereich des Prozesses?
+    fn is_used(&self, start: usize, end: usize) -> bool {
+        self.mappings.iter().any(|m| m.overlaps(start, end))
+    }
+
+    // Freien Bereich mit 'len' Bytes zwischen USER_MMAP_VM_START und den Stacks suchen (first fit),
+    // die Startadresse ist ein Vielfaches von 'align'
+    fn find_free_range(&self, len: usize, align: usize) -> Option<usize> {
+        let mut candidate = consts::USER_MMAP_VM_START;
+        let mut areas: Vec<&VmArea> = self
+            .mappings
+            .iter()
+            .filter(|m| m.end() > consts::USER_MMAP_VM_START)
+            .collect();
+        areas.sort_by_key(|m| m.start);
+
+        for area in areas {
+            if area.start >= candidate + len {
+                break;
+            }
+            candidate = candidate.max(area.end().next_multiple_of(align));
+        }
+
+        if candidate + len <= consts::USER_STACKS_VM_BOTTOM {
+            Some(candidate)
+        } else {
+            None
+        }
+    }
+
+    // Darf der Bereich, der 'vm_addr' enthaelt, dort geteilt werden? (nur an Seitengrenzen)
+    fn can_split_at(&self, vm_addr: usize) -> bool {
+        match self.find_mapping(vm_addr) {
+            Some(m) => vm_addr % m.page_size.bytes() == 0,
+            None => true,
+        }
+    }
+
+    // Bereich, der 'vm_addr' enthaelt, an 'vm_addr' in zwei Bereiche teilen
+    fn split_at(&mut self, vm_addr: usize) {
+        let pos = self
+            .mappings
+            .iter()
+            .position(|m| m.contains(vm_addr) && m.start != vm_addr);
+        if let Some(pos) = pos {
+            let lower = &mut self.mappings[pos];
+            let mut upper = *lower;
+            upper.start = vm_addr;
+            upper.len = lower.end() - vm_addr;
+            lower.len = vm_addr - lower.start;
+            self.mappings.push(upper);
+        }
+    }
+
+    // Startadresse fuer einen neuen Bereich mit 'len' Bytes: 'addr' pruefen (muss frei
+    // und auf 'align' aligniert sein) oder bei None einen freien Bereich suchen
+    fn choose_range(&self, addr: Option<usize>, len: usize, align: usize) -> Option<usize> {
+        match addr {
+            Some(start) => {
+                let end = start.checked_add(len)?;
+                if start % align != 0
+                    || start < consts::KERNEL_VM_SIZE
+                    || end > consts::USER_STACKS_VM_BOTTOM
+                    || self.is_used(start, end)
+                {
+                    return None;
+                }
+                Some(start)
+            }
+            None => self.find_free_range(len, align),
+        }
+    }
+
+    /**
+        Description: Create an anonymous area, backed on first touch with zeroed pages.
+
+        Parameters: \
+               `addr`      start address (`MAP_FIXED`) or `None` to let the kernel choose \
+               `len`       length in bytes, rounded up to full pages \
+               `prot`      access rights \
+               `page_size` 4 KB or 2 MB (`MAP_HUGETLB`), start and length are aligned to it
+
+        Return: \
+               start address or `None` if the range is invalid or already used
+    */
+    pub fn mmap(&mut self, addr: Option<usize>, len: usize, prot: VmProt, page_size: PageSize) -> Option<usize> {
+        if len == 0 || page_size == PageSize::Size1G {
+            return None;
+        }
+        let len = len.checked_next_multiple_of(page_size.bytes())?;
+        let start = self.choose_range(addr, len, page_size.bytes())?;
+
+        self.mappings.push(VmArea {
+            start,
+            len,
+            kind: VmKind::Mmap,
+            prot,
+            backing: Backing::Lazy,
+            page_size,
+        });
+        Some(start)
+    }
+
+    /**
+        Description: Remove the `mmap` areas in `[addr, addr + len)`, free their page \
+                     frames and invalidate the TLB entries.
+
+        Return: \
+               `false` if the range is not aligned (to 2 MB inside huge page areas) or \
+               contains other areas (e.g. a stack)
+    */
+    pub fn munmap(&mut self, addr: usize, len: usize) -> bool {
+        if len == 0 || addr % consts::PAGE_SIZE != 0 {
+            return false;
+        }
+        let end = match addr.checked_add(len.next_multiple_of(consts::PAGE_SIZE)) {
+            Some(end) => end,
+            None => return false,
+        };
+        if self.mappings.iter().any(|m| m.overlaps(addr, end) && m.kind != VmKind::Mmap) {
+            return false;
+        }
+        if !self.can_split_at(addr) || !self.can_split_at(end) {
+            return false;
+        }
+
+        self.split_at(addr);
+        self.split_at(end);
+
+        let pml4_addr = self.pml4_addr;
+        self.mappings.retain(|m| {
+            if m.overlaps(addr, end) {
+                pages::pg_unmap_user_range(pml4_addr, m.start, m.len / consts::PAGE_SIZE);
+                false
+            } else {
+                true
+            }
+        });
+        true
+    }
+
+    /**
+        Description: Take over the reference on the shared memory object `id` returned \
+                     by `shm::create`, it is dropped when the process exits.
+
+        Return: \
+               `false` if the process holds a handle already, the caller must drop \
+               the additional reference then
+    */
+    pub fn add_shm_handle(&mut self, id: usize) -> bool {
+        if self.has_shm_handle(id) {
+            return false;
+        }
+        self.shm_handles.push(id);
+        true
+    }
+
+    pub fn has_shm_handle(&self, id: usize) -> bool {
+        self.shm_handles.contains(&id)
+    }
+
+    /**
+        Description: Map the page frames of the shared memory object `id` (see `shm`). \
+                     Each page frame gets a further reference for this mapping.
+
+        Parameters: \
+               `addr`   start address or `None` to let the kernel choose \
+               `id`     shared memory object \
+               `frames` first page frame of the object (contiguous) \
+               `len`    length of the object in bytes (multiple of 4 KB) \
+               `prot`   access rights
+
+        Return: \
+               start address or `None` if the range is invalid or already used
+    */
+    pub fn map_shm(&mut self, addr: Option<usize>, id: usize, frames: PhysAddr, len: usize, prot: VmProt) -> Option<usize> {
+        let start = self.choose_range(addr, len, consts::PAGE_SIZE)?;
+
+        pages::pg_map_shared_range(
+            self.pml4_addr,
+            start,
+            frames,
+            len / consts::PAGE_SIZE,
+            !prot.is_empty(),
+            prot.contains(VmProt::WRITE),
+            prot.contains(VmProt::EXEC),
+        );
+        self.mappings.push(VmArea {
+            start,
+            len,
+            kind: VmKind::Shm { id },
+            prot,
+            backing: Backing::Eager,
+            page_size: PageSize::Size4K,
+        });
+        Some(start)
+    }
+
+    /**
+        Description: Remove the shared memory mapping starting at `addr`.
+
+        Return: \
+               id of the shared memory object or `None` if there is no such mapping
+    */
+    pub fn unmap_shm(&mut self, addr: usize) -> Option<usize> {
+        let pos = self
+            .mappings
+            .iter()
+            .position(|m| m.start == addr && matches!(m.kind, VmKind::Shm { .. }))?;
+        let area = self.mappings.remove(pos);
+        pages::pg_unmap_user_range(self.pml4_addr, area.start, area.len / consts::PAGE_SIZE);
+        match area.kind {
+            VmKind::Shm { id } => Some(id),
+            _ => None,
+        }
+    }
+
+    /**
+        Description: Check whether `[addr, addr + len)` lies completely in mappings \
+                     of this process which allow `prot` (e.g. for user buffers).
+    */
+    pub fn is_accessible(&self, addr: usize, len: usize, prot: VmProt) -> bool {
+        let end = match addr.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        let mut cur = addr;
+        while cur < end {
+            match self.find_mapping(cur) {
+                Some(m) if m.prot.contains(prot) => cur = m.end(),
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /**
+        Description: Change the access rights of `[addr, addr + len)`. The range must \
+                     be completely covered by `mmap` areas or segments of the program.
+
+        Return: \
+               `false` if the range is not aligned or not covered
+    */
+    pub fn mprotect(&mut self, addr: usize, len: usize, prot: VmProt) -> bool {
+        if len == 0 || addr % consts::PAGE_SIZE != 0 {
+            return false;
+        }
+        let end = match addr.checked_add(len.next_multiple_of(consts::PAGE_SIZE)) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        // Lueckenlos abgedeckt?
+        let mut cur = addr;
+        while cur < end {
+            match self.find_mapping(cur) {
+                Some(m) if m.kind == VmKind::Mmap || m.kind == VmKind::Image => cur = m.end(),
+                _ => return false,
+            }
+        }
+        if !self.can_split_at(addr) || !self.can_split_at(end) {
+            return false;
+        }
+
+        self.split_at(addr);
+        self.split_at(end);
+
+        for m in self.mappings.iter_mut().filter(|m| m.overlaps(addr, end)) {
+            m.prot = prot;
+            pages::pg_protect_user_range(
+                self.pml4_addr,
+                m.start,
+                m.len / consts::PAGE_SIZE,
+                !prot.is_empty(),
+                prot.contains(VmProt::WRITE),
+                prot.contains(VmProt::EXEC),
+            );
+        }
+        true
+    }
+
+    /**
+        Description: Register thread `tid` and reserve a user stack slot for it. \
+                     The stack is not mapped, pages are backed on first touch.
+
+        Parameters: \
+               `tid` new thread of this process
+
+        Return: \
+               virtual start address of the user stack of `tid`
+    */
+    pub fn add_thread(&mut self, tid: usize) -> usize {
+        // kleinsten freien Slot suchen
+        let mut slot = 0;
+        while self.stack_slots.contains(&slot) {
+            slot += 1;
+        }
+        assert!(slot < consts::MAX_USER_STACKS, "add_thread: zu viele Threads im Prozess!");
+        self.stack_slots.push(slot);
+
+        // Stack waechst abwaerts, die Guard-Page liegt darunter
+        let top = consts::USER_STACK_VM_START - slot * consts::USER_STACK_STRIDE;
+        let start = top - consts::USER_STACK_SIZE;
+        self.mappings.push(VmArea {
+            start,
+            len: consts::USER_STACK_SIZE,
+            kind: VmKind::Stack { tid },
+            prot: VmProt::READ | VmProt::WRITE,
+            backing: Backing::Lazy,
+            page_size: PageSize::Size4K,
+        });
+        self.mappings.push(VmArea {
+            start: start - consts::PAGE_SIZE,
+            len: consts::PAGE_SIZE,
+            kind: VmKind::StackGuard { tid },
+            prot: VmProt::empty(),
+            backing: Backing::Eager,
+            page_size: PageSize::Size4K,
+        });
+        self.threads.push(tid);
+
+        start
+    }
+
+    // Startadresse des User-Stacks von 'tid'
+    pub fn get_stack_start(&self, tid: usize) -> Option<usize> {
+        self.mappings
+            .iter()
+            .find(|m| m.kind == VmKind::Stack { tid })
+            .map(|m| m.start)
+    }
+
+    /**
+        Description: Create a copy of this process for `fork`. The child gets all areas \
+                     except the stacks of other threads, the stack of `parent_tid` is \
+                     used by `child_tid` at the same address. Page frames are shared, \
+                     all pages except shm become copy-on-write in both address spaces.
+
+        Parameters: \
+               `parent_tid` thread calling `fork` \
+               `child_tid`  only thread of the child
+
+        Return: \
+               the new process
+    */
+    pub fn fork(&mut self, parent_tid: usize, child_tid: usize) -> Arc<KMutex<Process>> {
+        let child = Process::new();
+        {
+            let mut c = child.lock();
+            c.heap_start = self.heap_start;
+            c.brk = self.brk;
+
+            for area in self.mappings.iter() {
+                let mut copy = *area;
+                match area.kind {
+                    VmKind::Stack { tid } | VmKind::StackGuard { tid } if tid != parent_tid => continue,
+                    VmKind::Stack { .. } => {
+                        copy.kind = VmKind::Stack { tid: child_tid };
+                        let slot = (consts::USER_STACK_VM_START - area.end()) / consts::USER_STACK_STRIDE;
+                        c.stack_slots.push(slot);
+                    }
+                    VmKind::StackGuard { .. } => copy.kind = VmKind::StackGuard { tid: child_tid },
+                    VmKind::Shm { id } => shm::retain(id),
+                    _ => {}
+                }
+                // Shared Memory bleibt geteilt, alles andere wird Copy-on-Write
+                let cow = !matches!(area.kind, VmKind::Shm { .. });
+                pages::pg_share_range(self.pml4_addr, c.pml4_addr, area.start, area.len / consts::PAGE_SIZE, cow);
+                c.mappings.push(copy);
+            }
+            c.threads.push(child_tid);
+
+            for id in self.shm_handles.iter() {
+                shm::retain(*id);
+                c.shm_handles.push(*id);
+            }
+        }
+        child
+    }
+
+    /**
+        Description: Remove thread `tid`, unmap its user stack and release the slot.
+
+        Parameters: \
+               `tid` thread which is released
+    */
+    pub fn remove_thread(&mut self, tid: usize) {
+        self.threads.retain(|t| *t != tid);
+
+        let pos = self
+            .mappings
+            .iter()
+            .position(|m| m.kind == VmKind::Stack { tid });
+        if let Some(pos) = pos {
+            let stack = self.mappings.remove(pos);
+            pages::pg_unmap_user_range(self.pml4_addr, stack.start, stack.len / consts::PAGE_SIZE);
+
+            let slot = (consts::USER_STACK_VM_START - stack.end()) / consts::USER_STACK_STRIDE;
+            self.stack_slots.retain(|s| *s != slot);
+        }
+        self.mappings.retain(|m| m.kind != VmKind::StackGuard { tid });
+    }
+}
+
+// Der letzte Thread wurde freigegeben -> Adressraum abbauen
+impl Drop for Process {
+    fn drop(&mut self) {
+        pages::pg_free_tables(self.pml4_addr);
+
+        // Eingeblendete Shared-Memory-Objekte verlieren eine Referenz, ebenso die Handles
+        for area in self.mappings.iter() {
+            if let VmKind::Shm { id } = area.kind {
+                shm::release(id);
+            }
+        }
+        for id in self.shm_handles.iter() {
+            shm::release(*id);
+        }
+
+        // Zum Erkennen von Lecks (nur im Debug-Modus des Heaps): Zaehler und
+        // belegte Bloecke nach der Freigabe
+        #[cfg(feature = "debug_heap")]
+        {
+            kprintln!("Process::drop, pid={}", self.pid);
+            frames::pf_dump_stats();
+            allocator::check_heap();
+            allocator::dump_leaks();
+        }
+    }
+}
@@ -1,21 +1,36 @@
 /* ╔═════════════════════════════════════════════════════════════════════════╗
    ║ Module: scheduler                                                       ║
    ╟─────────────────────────────────────────────────────────────────────────╢
-   ║ Descr.: A basic round-robin scheduler for cooperative threads.          ║
-   ║         No priorties supported.                                         ║
+   ║ Descr.: Scheduler for preemptive threads. Which ready thread runs next  ║
+   ║         is decided by a pluggable 'SchedPolicy' (round robin by         ║
+   ║         default, see 'policy'). Threads waiting for an event are parked ║
+   ║         in a 'WaitQueue' using 'block' and released by 'wake_one' or    ║
+   ║         'wake_all' (may be called from ISRs). Sleeping threads are kept ║
+   ║         sorted by wake-up tick and are woken by the PIT.                ║
+   ║                                                                         ║
+   ║         The scheduler lock is only taken with interrupts disabled,      ║
+   ║         otherwise an ISR calling 'wake_*' could deadlock.               ║
    ╟─────────────────────────────────────────────────────────────────────────╢
    ║ Autor:  Michael Schoettner, HHU, 14.6.2024                              ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
 use core::ptr;
 use core::sync::atomic::AtomicUsize;
 use spin::Mutex;
 
 use crate::devices::cga;
+use crate::devices::pit;
 use crate::kernel::cpu;
+use crate::kernel::threads::kmutex::KMutex;
+use crate::kernel::threads::policy::round_robin::RoundRobin;
+use crate::kernel::threads::policy::{ReadyReason, SchedPolicy};
+use crate::kernel::threads::process::Process;
 use crate::kernel::threads::thread;
-use crate::mylib::queue;
+use crate::kernel::threads::wait_queue::WaitQueue;
 
 static THREAD_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
@@ -25,11 +40,64 @@ pub fn next_thread_id() -> usize {
 
 pub static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
 
+// Threads, die in 'join' auf das Ende eines anderen Threads warten
+static JOINERS: WaitQueue = WaitQueue::new();
+
+// Hoechstens so viele Exit-Codes werden fuer 'join' aufbewahrt, danach
+// wird der aelteste verworfen
+const MAX_EXIT_CODES: usize = 64;
+
 /**
  Description: Return callers thread ID
 */
 pub fn get_active_tid() -> usize {
-    thread::Thread::get_tid(SCHEDULER.lock().active)
+    let irq = cpu::disable_int_nested();
+    let tid = thread::Thread::get_tid(SCHEDULER.lock().active);
+    cpu::enable_int_nested(irq);
+    tid
+}
+
+/**
+ Description: Return callers thread ID or `None` if no thread runs yet (boot)
+*/
+pub fn try_get_active_tid() -> Option<usize> {
+    let irq = cpu::disable_int_nested();
+    let active = SCHEDULER.lock().active;
+    let tid = if active.is_null() { None } else { Some(thread::Thread::get_tid(active)) };
+    cpu::enable_int_nested(irq);
+    tid
+}
+
+/**
+ Description: Return the user registers of the running thread at its current
+              system call (see `Thread::get_syscall_context`), used by `fork`
+*/
+pub fn get_active_syscall_context() -> thread::UserContext {
+    let irq = cpu::disable_int_nested();
+    let context = unsafe { (*SCHEDULER.lock().active).get_syscall_context() };
+    cpu::enable_int_nested(irq);
+    context
+}
+
+/**
+ Description: Return the process of the running thread. Uses `try_lock`, because
+              it is called from the page-fault handler, which may interrupt the
+              kernel while the scheduler is locked.
+
+ Return: \
+        process or `None` if no thread is running or the scheduler is locked
+*/
+pub fn get_active_process() -> Option<Arc<KMutex<Process>>> {
+    let irq = cpu::disable_int_nested();
+    let process = SCHEDULER.try_lock().and_then(|sched| {
+        if sched.active.is_null() {
+            None
+        } else {
+            Some(unsafe { (*sched.active).get_process().clone() })
+        }
+    });
+    cpu::enable_int_nested(irq);
+    process
 }
 
 /**
@@ -51,12 +119,18 @@ pub fn get_active() -> Box<thread::Thread> {
  Description: Set initialized flag
 */
 pub fn set_initialized() {
+    let irq = cpu::disable_int_nested();
     SCHEDULER.lock().initialized = true;
+    cpu::enable_int_nested(irq);
 }
 
 pub struct Scheduler {
     active: *mut thread::Thread,
-    ready_queue: queue::Queue<Box<thread::Thread>>, // auf die CPU wartende Threads
+    policy: Option<Box<dyn SchedPolicy>>,           // verwaltet die auf die CPU wartenden Threads
+    threads: Vec<*mut thread::Thread>,              // alle Threads bis zu ihrer Freigabe (fuer 'get_stats')
+    sleepers: Vec<(u64, Box<thread::Thread>)>,      // schlafende Threads, sortiert nach Weck-Tick
+    zombies: Vec<Box<thread::Thread>>,              // beendete Threads, Ressourcen noch belegt
+    exit_codes: Vec<(usize, i64)>,                  // (tid, exit_code) freigegebener Threads ohne 'join'
     next_thread_id: u64,
     initialized: bool,
 }
@@ -70,7 +144,11 @@ impl Scheduler {
         Scheduler {
             active: ptr::null_mut(),
             next_thread_id: 0,
-            ready_queue: queue::Queue::new(),
+            policy: None,
+            threads: Vec::new(),
+            sleepers: Vec::new(),
+            zombies: Vec::new(),
+            exit_codes: Vec::new(),
             initialized: false,
         }
     }
@@ -79,15 +157,9 @@ impl Scheduler {
      Description: Start the scheduler. Called only once from 'startup'
     */
     pub fn schedule() {
-        let next_thread = SCHEDULER.lock().ready_queue.dequeue();
-        if let Some(that) = next_thread {
-            // convert 'next_thread' into raw pointer.
-            // Prevents Rust from deleting it too early but we need to manually call 'drop' later
-            let raw = Box::into_raw(that);
-
-            // set active reference in SCHEDULER
-            SCHEDULER.lock().active = raw;
-
+        cpu::disable_int();
+        let next_thread = SCHEDULER.lock().dispatch_next();
+        if let Some(raw) = next_thread {
             // and start this thread
             thread::Thread::start(raw);
         } else {
@@ -95,61 +167,525 @@ impl Scheduler {
         }
     }
 
+    /**
+        Description: Take the next thread from the ready queue and make it the
+                     active one. The caller has to take care of the current
+                     thread and must switch to the returned thread.
+
+        Return: \
+               raw pointer to the next thread or `None` if the ready queue is empty
+    */
+    fn dispatch_next(&mut self) -> Option<*mut thread::Thread> {
+        let next = self.policy().dequeue()?;
+
+        // convert 'next' into raw pointer.
+        // Prevents Rust from deleting it too early but we need to manually call 'drop' later
+        let raw = Box::into_raw(next);
+        unsafe {
+            (*raw).set_state(thread::ThreadState::Running);
+            (*raw).start_slice();
+        }
+
+        // set active reference in SCHEDULER
+        self.active = raw;
+        Some(raw)
+    }
+
+    // Aktive Strategie, ohne Auswahl beim Booten Round Robin
+    fn policy(&mut self) -> &mut dyn SchedPolicy {
+        self.policy
+            .get_or_insert_with(|| Box::new(RoundRobin::new()))
+            .as_mut()
+    }
+
+    /**
+        Description: Replace the scheduling policy. Threads already in the
+                     ready queue are handed over to the new policy.
+
+        Parameters: \
+               `policy` new policy, e.g. created by `PolicyKind::create`
+    */
+    pub fn set_policy(policy: Box<dyn SchedPolicy>) {
+        let irq = cpu::disable_int_nested();
+        let mut sched = SCHEDULER.lock();
+        let mut old = sched.policy.replace(policy);
+        if let Some(old) = old.as_mut() {
+            while let Some(that) = old.dequeue() {
+                sched.policy().enqueue(that, ReadyReason::New);
+            }
+        }
+        kprintln!("scheduler: policy = {}", sched.policy().name());
+        drop(sched);
+        cpu::enable_int_nested(irq);
+        drop(old);
+    }
+
+    /**
+        Description: Check if any thread is ready to run (used by the idle thread).
+    */
+    pub fn has_ready_threads() -> bool {
+        let irq = cpu::disable_int_nested();
+        let ready = !SCHEDULER.lock().policy().is_empty();
+        cpu::enable_int_nested(irq);
+        ready
+    }
+
     /**
         Description: Register new thread in ready queue
 
         Parameters: \
                `that` thread to be registered
     */
-    pub fn ready(that: Box<thread::Thread>) {
-        SCHEDULER.lock().ready_queue.enqueue(that);
+    pub fn ready(mut that: Box<thread::Thread>) {
+        that.set_state(thread::ThreadState::Ready);
+
+        let irq = cpu::disable_int_nested();
+        let mut sched = SCHEDULER.lock();
+        sched.threads.push(that.get_raw_pointer());
+        sched.policy().enqueue(that, ReadyReason::New);
+        drop(sched);
+        cpu::enable_int_nested(irq);
+    }
+
+    /**
+        Description: Block the calling thread in `wq` and switch to the next
+                     thread. Returns after the thread has been released by
+                     `wake_one` or `wake_all`. \
+                     To avoid lost wakeups the caller should disable interrupts
+                     before checking its wait condition and calling `block`.
+
+        Parameters: \
+               `wq` wait queue the calling thread is parked in
+    */
+    pub fn block(wq: &WaitQueue) {
+        let irq = cpu::disable_int_nested();
+
+        let (that, next) = {
+            let mut sched = SCHEDULER.lock();
+            let that = sched.active;
+
+            // Mindestens der Idle-Thread muss bereit sein
+            let next = sched.dispatch_next();
+            if next.is_none() {
+                panic!("Cannot block thread as there is no other thread to run!");
+            }
+
+            unsafe {
+                (*that).count_switch(true);
+                (*that).set_state(thread::ThreadState::Blocked);
+                wq.enqueue(Box::from_raw(that));
+            }
+            (that, next.unwrap())
+        };
+
+        thread::Thread::switch(that, next);
+
+        // Hier geht es nach 'wake_*' weiter
+        cpu::enable_int_nested(irq);
+    }
+
+    /**
+        Description: Put a blocked thread back into the ready queue.
+
+        Parameters: \
+               `that` thread taken out of a wait queue
+    */
+    pub fn deblock(mut that: Box<thread::Thread>) {
+        that.set_state(thread::ThreadState::Ready);
+
+        let irq = cpu::disable_int_nested();
+        SCHEDULER.lock().policy().enqueue(that, ReadyReason::Woken);
+        cpu::enable_int_nested(irq);
+    }
+
+    /**
+        Description: Release the first thread waiting in `wq`.
+
+        Parameters: \
+               `wq` wait queue
+
+        Return: \
+               `true` if a thread has been released
+    */
+    pub fn wake_one(wq: &WaitQueue) -> bool {
+        let irq = cpu::disable_int_nested();
+        let woken = match wq.dequeue() {
+            Some(that) => {
+                Scheduler::deblock(that);
+                true
+            }
+            None => false,
+        };
+        cpu::enable_int_nested(irq);
+        woken
+    }
+
+    /**
+        Description: Release all threads waiting in `wq`.
+
+        Parameters: \
+               `wq` wait queue
+
+        Return: \
+               number of released threads
+    */
+    pub fn wake_all(wq: &WaitQueue) -> usize {
+        let irq = cpu::disable_int_nested();
+        let mut count = 0;
+        while let Some(that) = wq.dequeue() {
+            Scheduler::deblock(that);
+            count += 1;
+        }
+        cpu::enable_int_nested(irq);
+        count
+    }
+
+    /**
+        Description: Let the calling thread sleep until the system time
+                     (see `pit::get_systime`) has reached `tick`.
+
+        Parameters: \
+               `tick` wake-up time in ticks
+    */
+    pub fn sleep_until(tick: u64) {
+        let irq = cpu::disable_int_nested();
+
+        let switch = {
+            let mut sched = SCHEDULER.lock();
+            let that = sched.active;
+
+            if tick <= pit::get_systime() {
+                None
+            } else {
+                // Mindestens der Idle-Thread muss bereit sein
+                let next = sched.dispatch_next();
+                if next.is_none() {
+                    panic!("Cannot sleep as there is no other thread to run!");
+                }
+
+                // nach Weck-Tick sortiert einfuegen (hinter gleiche Ticks)
+                let pos = sched.sleepers.partition_point(|(t, _)| *t <= tick);
+                unsafe {
+                    (*that).count_switch(true);
+                    (*that).set_state(thread::ThreadState::Blocked);
+                    sched.sleepers.insert(pos, (tick, Box::from_raw(that)));
+                }
+                Some((that, next.unwrap()))
+            }
+        };
+
+        if let Some((that, next)) = switch {
+            thread::Thread::switch(that, next);
+        }
+        cpu::enable_int_nested(irq);
+    }
+
+    /**
+        Description: Let the calling thread sleep for at least `ms` milliseconds.
+
+        Parameters: \
+               `ms` sleep time in milliseconds (rounded up to full ticks)
+    */
+    pub fn sleep_ms(ms: u64) {
+        Scheduler::sleep_until(pit::get_systime() + pit::ms_to_ticks(ms));
+    }
+
+    /**
+        Description: Called by the ISR of the PIT on every tick. Moves all
+                     sleeping threads with an expired wake-up tick into the
+                     ready queue.
+
+        Parameters: \
+               `now` current system time in ticks
+    */
+    pub fn wake_sleepers(&mut self, now: u64) {
+        // Faellige Threads einzeln vorne entnehmen, ohne Zwischenliste
+        while self.sleepers.first().map_or(false, |(t, _)| *t <= now) {
+            let (_, mut that) = self.sleepers.remove(0);
+            that.set_state(thread::ThreadState::Ready);
+            self.policy().enqueue(that, ReadyReason::Woken);
+        }
     }
 
     /**
         Description: Calling thread terminates. Scheduler switches to next thread.
-                     (The thread terminating is not in the ready queue.)
+                     (The thread terminating is not in the ready queue.) \
+                     The thread becomes a zombie; its stacks and page tables are
+                     released later by `reap` or `join`, running on another thread.
+
+        Parameters: \
+               `exit_code` handed over to a thread calling `join`
     */
-    pub fn exit() {
-        // Get next thread from ready queue
-        let next = SCHEDULER.lock().ready_queue.dequeue();
-        if next.is_none() {
-            panic!("Cannot exit thread as there is no other thread to run!");
+    pub fn exit(exit_code: i64) -> ! {
+        // Interrupts bleiben aus, bis der naechste Thread laeuft (dessen
+        // RFLAGS werden in '_thread_switch' wiederhergestellt)
+        cpu::disable_int();
+
+        // Threads in 'join' pruefen erneut, ob ihr Thread beendet ist
+        Scheduler::wake_all(&JOINERS);
+
+        let (that, next) = {
+            let mut sched = SCHEDULER.lock();
+            let that = sched.active;
+
+            // Get next thread from ready queue
+            let next = sched.dispatch_next();
+            if next.is_none() {
+                panic!("Cannot exit thread as there is no other thread to run!");
+            }
+
+            // Calling thread becomes a zombie
+            unsafe {
+                (*that).count_switch(true);
+                (*that).set_zombie(exit_code);
+                sched.zombies.push(Box::from_raw(that));
+            }
+            (that, next.unwrap())
+        };
+
+        // Der Zombie wird nie wieder eingelastet
+        thread::Thread::switch(that, next);
+        panic!("exit: zombie thread was resumed!");
+    }
+
+    /**
+        Description: Reaper. Releases stacks and page tables of all zombies. \
+                     Exit codes are kept until the thread is joined (at most \
+                     `MAX_EXIT_CODES`). Must not be called by a zombie itself \
+                     (see `exit`). Never blocks: \
+                     zombies whose process is locked are left for the next call.
+    */
+    pub fn reap() {
+        let irq = cpu::disable_int_nested();
+        let zombies = {
+            let mut sched = SCHEDULER.lock();
+            let (zombies, busy): (Vec<_>, Vec<_>) = mem::take(&mut sched.zombies)
+                .into_iter()
+                .partition(|z| z.get_process().owner().is_none());
+            sched.zombies = busy;
+            for z in zombies.iter() {
+                let tid = thread::Thread::get_tid(z.as_ref());
+                if sched.exit_codes.len() == MAX_EXIT_CODES {
+                    sched.exit_codes.remove(0);
+                }
+                sched.exit_codes.push((tid, z.get_exit_code()));
+                sched.forget(tid);
+            }
+            zombies
+        };
+
+        // Freigabe ausserhalb des Scheduler-Locks (ruft 'Drop' von 'Thread').
+        // Die Interrupts bleiben aus, damit kein anderer Thread den Prozess
+        // dazwischen sperrt, denn der Idle-Thread darf nicht blockieren.
+        drop(zombies);
+        cpu::enable_int_nested(irq);
+    }
+
+    // Thread 'tid' in der Liste aller Threads suchen
+    fn find(&self, tid: usize) -> Option<*mut thread::Thread> {
+        self.threads
+            .iter()
+            .copied()
+            .find(|t| thread::Thread::get_tid(*t) == tid)
+    }
+
+    // Thread 'tid' wird freigegeben, aus der Liste aller Threads entfernen
+    fn forget(&mut self, tid: usize) {
+        self.threads.retain(|t| thread::Thread::get_tid(*t) != tid);
+    }
+
+    // tid des laufenden Threads, None falls der Scheduler noch nicht laeuft
+    // (fuer Fehlermeldungen, z.B. bei einem Page-Fault)
+    pub fn active_tid(&self) -> Option<usize> {
+        if self.active.is_null() {
+            None
+        } else {
+            Some(thread::Thread::get_tid(self.active))
         }
+    }
 
-        // Start next thread
-        if let Some(nx) = next {
-            let raw = Box::into_raw(nx);
-            SCHEDULER.lock().active = raw;
-            thread::Thread::start(raw);
+    /**
+        Description: Called by the ISR of the PIT on every tick. Charges the
+                     tick to the running thread.
+    */
+    pub fn account_tick(&mut self) {
+        if self.initialized && !self.active.is_null() {
+            unsafe { (*self.active).account_tick() };
         }
     }
 
     /**
-        Description: Yield cpu and switch to next thread
+        Description: Get the CPU accounting of thread `tid`.
+
+        Parameters: \
+               `tid` thread to query
+
+        Return: \
+               counters of the thread or `None` if there is no such thread
     */
-    pub fn yield_cpu() {
-        // Get next thread from ready queue
-        let next = SCHEDULER.lock().ready_queue.dequeue();
-        if next.is_none() {
-            return;
+    pub fn get_stats(tid: usize) -> Option<thread::ThreadStats> {
+        let irq = cpu::disable_int_nested();
+        let sched = SCHEDULER.lock();
+        let stats = sched.find(tid).map(|t| unsafe { (*t).get_stats() });
+        drop(sched);
+        cpu::enable_int_nested(irq);
+        stats
+    }
+
+    /**
+        Description: Set the time slice of thread `tid`.
+
+        Parameters: \
+               `tid`     thread \
+               `quantum` time slice in ticks (at least 1)
+
+        Return: \
+               `false` if there is no such thread
+    */
+    pub fn set_quantum(tid: usize, quantum: usize) -> bool {
+        let irq = cpu::disable_int_nested();
+        let sched = SCHEDULER.lock();
+        let found = match sched.find(tid) {
+            Some(t) => {
+                unsafe { (*t).set_quantum(quantum) };
+                true
+            }
+            None => false,
+        };
+        drop(sched);
+        cpu::enable_int_nested(irq);
+        found
+    }
+
+    /**
+        Description: Print the CPU accounting of all threads.
+    */
+    pub fn dump_stats() {
+        let irq = cpu::disable_int_nested();
+        let sched = SCHEDULER.lock();
+        kprintln!("  tid  state     quantum   ticks   vol. sw.  invol. sw.");
+        for t in sched.threads.iter() {
+            let stats = unsafe { (**t).get_stats() };
+            let state = unsafe { (**t).get_state() };
+            kprintln!(
+                "{:5}  {:8?} {:8} {:7} {:10} {:11}",
+                stats.tid,
+                state,
+                stats.quantum,
+                stats.ticks_run,
+                stats.voluntary_switches,
+                stats.involuntary_switches
+            );
         }
+        drop(sched);
+        cpu::enable_int_nested(irq);
+    }
 
-        let that = SCHEDULER.lock().active;
+    /**
+        Description: Wait until thread `tid` has terminated and return its exit code.
 
-        // Re-insert current thread into ready queue
-        let bx;
-        unsafe {
-            // convert raw-Pointer back to Box<Thread>
-            bx = Box::from_raw(that);
+        Parameters: \
+               `tid` thread to wait for
+
+        Return: \
+               `Some(exit_code)` or `None` if there is no such thread (or it was joined \
+               already, or its exit code was dropped, see `MAX_EXIT_CODES`)
+    */
+    pub fn join(tid: usize) -> Option<i64> {
+        loop {
+            let irq = cpu::disable_int_nested();
+            let mut sched = SCHEDULER.lock();
+
+            // Auf sich selbst zu warten, wuerde nie enden
+            if thread::Thread::get_tid(sched.active) == tid {
+                drop(sched);
+                cpu::enable_int_nested(irq);
+                return None;
+            }
+
+            // Thread schon freigegeben?
+            if let Some(pos) = sched.exit_codes.iter().position(|(t, _)| *t == tid) {
+                let (_, exit_code) = sched.exit_codes.remove(pos);
+                drop(sched);
+                cpu::enable_int_nested(irq);
+                return Some(exit_code);
+            }
+
+            // Thread ein Zombie? Dann geben wir ihn gleich hier frei
+            let pos = sched
+                .zombies
+                .iter()
+                .position(|z| thread::Thread::get_tid(z.as_ref()) == tid);
+            if let Some(pos) = pos {
+                let zombie = sched.zombies.remove(pos);
+                sched.forget(tid);
+                drop(sched);
+                cpu::enable_int_nested(irq);
+                let exit_code = zombie.get_exit_code();
+                drop(zombie);
+                return Some(exit_code);
+            }
+
+            // Thread existiert gar nicht?
+            let alive = sched.find(tid).is_some();
+            drop(sched);
+            if !alive {
+                cpu::enable_int_nested(irq);
+                return None;
+            }
+
+            // Thread laeuft noch -> blockieren bis ein Thread terminiert
+            Scheduler::block(&JOINERS);
+            cpu::enable_int_nested(irq);
         }
-        SCHEDULER.lock().ready_queue.enqueue(bx);
+    }
+
+    /**
+        Description: Set the static priority of the calling thread (see
+                     `StaticPriority`) and let a thread with a higher priority run.
+
+        Parameters: \
+               `priority` 1 .. `PRIO_LEVELS` - 1 (0 is reserved for the idle thread)
+    */
+    pub fn set_priority(priority: usize) {
+        let irq = cpu::disable_int_nested();
+        unsafe { (*SCHEDULER.lock().active).set_priority(priority) };
+        cpu::enable_int_nested(irq);
+
+        Scheduler::yield_cpu();
+    }
+
+    /**
+        Description: Yield cpu and switch to next thread
+    */
+    pub fn yield_cpu() {
+        let irq = cpu::disable_int_nested();
+
+        let switch = {
+            let mut sched = SCHEDULER.lock();
+            let that = sched.active;
+
+            // Get next thread from ready queue
+            match sched.dispatch_next() {
+                None => None,
+                Some(next) => {
+                    // Re-insert current thread into ready queue
+                    unsafe {
+                        (*that).count_switch(true);
+                        (*that).set_state(thread::ThreadState::Ready);
+                        // convert raw-Pointer back to Box<Thread>
+                        sched.policy().enqueue(Box::from_raw(that), ReadyReason::Yielded);
+                    }
+                    Some((that, next))
+                }
+            }
+        };
 
         // Switch thread
-        if let Some(nx) = next {
-            let raw = Box::into_raw(nx);
-            SCHEDULER.lock().active = raw;
-            thread::Thread::switch(that, raw);
+        if let Some((that, next)) = switch {
+            thread::Thread::switch(that, next);
         }
+        cpu::enable_int_nested(irq);
     }
 
     /**
@@ -168,28 +704,29 @@ impl Scheduler {
             return (ptr::null_mut(), ptr::null_mut());
         }
 
-        // Check if there is a thread in the ready queue, if not we abort
-        let next = self.ready_queue.dequeue();
-        if next.is_none() {
+        // Ask the policy whether the current thread has to be preempted
+        let current = self.active;
+        if !self.policy().tick(unsafe { &mut *current }) {
             return (ptr::null_mut(), ptr::null_mut());
         }
 
+        // Check if there is a thread in the ready queue, if not we abort
+        let next = match self.dispatch_next() {
+            Some(next) => next,
+            None => return (ptr::null_mut(), ptr::null_mut()),
+        };
+
         // If we are here, we can preempt
 
         // Insert the current running thread into the ready qeueue
-        let current = self.active;
         unsafe {
-            self.ready_queue.enqueue(Box::from_raw(current));
+            (*current).count_switch(false);
+            (*current).set_state(thread::ThreadState::Ready);
+            self.policy().enqueue(Box::from_raw(current), ReadyReason::Preempted);
         }
 
-        // Set active thread in scheduler and return (current, next)
-        if let Some(nx) = next {
-            let raw_next = Box::into_raw(nx);
-            self.active = raw_next;
-            (current, raw_next)
-        } else {
-            panic!("prepare_preempt failed.");
-        }
+        // Active thread has been set by 'dispatch_next', return (current, next)
+        (current, next)
 
         // Interrupts werden in Thread_switch in thread.asm wieder zugelassen
         //
@@ -0,0 +1,81 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: semaphore                                                       ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Counting semaphore. 'acquire' blocks the calling thread while   ║
+   ║         the counter is zero, 'release' increments the counter and wakes ║
+   ║         one waiting thread. 'release' may be called from an ISR.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use spin::Mutex;
+
+use crate::kernel::cpu;
+use crate::kernel::threads::scheduler::Scheduler;
+use crate::kernel::threads::wait_queue::WaitQueue;
+
+pub struct Semaphore {
+    count: Mutex<usize>,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Semaphore {
+            count: Mutex::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /**
+        Description: Decrement the counter, block while it is zero (P).
+    */
+    pub fn acquire(&self) {
+        loop {
+            let irq = cpu::disable_int_nested();
+            if self.try_acquire() {
+                cpu::enable_int_nested(irq);
+                return;
+            }
+            Scheduler::block(&self.waiters);
+            cpu::enable_int_nested(irq);
+        }
+    }
+
+    /**
+        Description: Decrement the counter without blocking.
+
+        Return: \
+               `false` if the counter is zero
+    */
+    pub fn try_acquire(&self) -> bool {
+        let irq = cpu::disable_int_nested();
+        let acquired = {
+            let mut count = self.count.lock();
+            if *count > 0 {
+                *count -= 1;
+                true
+            } else {
+                false
+            }
+        };
+        cpu::enable_int_nested(irq);
+        acquired
+    }
+
+    /**
+        Description: Increment the counter and wake one waiting thread (V).
+    */
+    pub fn release(&self) {
+        let irq = cpu::disable_int_nested();
+        *self.count.lock() += 1;
+        Scheduler::wake_one(&self.waiters);
+        cpu::enable_int_nested(irq);
+    }
+
+    // aktueller Zaehlerstand
+    pub fn count(&self) -> usize {
+        let irq = cpu::disable_int_nested();
+        let count = *self.count.lock();
+        cpu::enable_int_nested(irq);
+        count
+    }
+}
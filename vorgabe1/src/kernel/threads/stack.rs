@@ -13,13 +13,12 @@ use core::fmt;
 use crate::consts;
 use crate::kernel::allocator;
 use crate::kernel::cpu;
-use crate::kernel::paging::frames::PhysAddr;
-use crate::kernel::paging::pages::pg_mmap_user_stack;
 
 #[repr(C)]
 pub struct Stack {
     data: *mut u8,
     size: usize,
+    is_kernel_stack: bool, // Kernel-Stacks liegen im Heap, User-Stacks nur in den Page-Tables
 }
 
 impl Stack {
@@ -44,58 +43,69 @@ impl Stack {
         Box::new(Stack { data, size })
     }*/
     
-    //TODO
-    pub fn new(size: usize, kernel_stack: bool, pml4_addr: PhysAddr) -> Box<Stack> {  
-        if kernel_stack{ // wie zuvor für kernel
-            // 64 bit alignment for stack
-            let layout = unsafe { Layout::from_size_align_unchecked(size, consts::STACK_ALIGNMENT) };
-
-            // alloc memory for stack and set ptr. to end of block - consts::STACK_ENTRY_SIZE
-            let start = allocator::alloc(layout);
-            let data = ((start as usize) + (size as usize) - consts::STACK_ENTRY_SIZE) as *mut u8;
-            if data.is_null() {
-                println!("Panic: failed in 'Stack::new::kernel_stack'");
-                cpu::halt();
-            }
-
-            kprintln!(
-                "Stack::new, memory block = [0x{:x}; 0x{:x}]",
-                start as usize,
-                (data as usize + consts::STACK_ENTRY_SIZE)
-            );
-
-            Box::new(Stack { data, size }) 
-        } 
-        else // für user thread muss mapping in pages erstellt werden
-        { 
-            // fordere einen neuen stack an im virt raum (64 TiB bis 64 TiB + 64 KiB) 
-            let start = pg_mmap_user_stack(pml4_addr);
-            let data = ((start as usize) + (size as usize) - consts::STACK_ENTRY_SIZE) as *mut u8;
-            if data.is_null() {
-                println!("Panic: failed in 'Stack::new::user_stack'");
-                cpu::halt();
-            }
-
-            kprintln!(
-                "Stack::new, memory block = [0x{:x}; 0x{:x}]",
-                start as usize,
-                (data as usize + consts::STACK_ENTRY_SIZE)
-            );
-
-            Box::new(Stack { data, size }) 
-        }        
-    } 
+    // Kernel-Stack im Heap anlegen, User-Stacks legt 'new_user' an
+    pub fn new(size: usize) -> Box<Stack> {
+        // 64 bit alignment for stack
+        let layout = unsafe { Layout::from_size_align_unchecked(size, consts::STACK_ALIGNMENT) };
+
+        // alloc memory for stack and set ptr. to end of block - consts::STACK_ENTRY_SIZE
+        let start = allocator::alloc(layout);
+        let data = ((start as usize) + (size as usize) - consts::STACK_ENTRY_SIZE) as *mut u8;
+        if data.is_null() {
+            println!("Panic: failed in 'Stack::new::kernel_stack'");
+            cpu::halt();
+        }
+
+        kprintln!(
+            "Stack::new, memory block = [0x{:x}; 0x{:x}]",
+            start as usize,
+            (data as usize + consts::STACK_ENTRY_SIZE)
+        );
+
+        Box::new(Stack { data, size, is_kernel_stack: true })
+    }
+
+    // User-Stack an der virtuellen Adresse 'vm_start' anlegen
+    // (jeder Thread eines Prozesses hat einen eigenen Bereich, siehe 'process').
+    // Es wird nichts gemappt, die Page-Frames alloziert der Page-Fault-Handler
+    // beim ersten Zugriff.
+    pub fn new_user(size: usize, vm_start: usize) -> Box<Stack> {
+        let start = vm_start as *mut u8;
+        let data = ((start as usize) + (size as usize) - consts::STACK_ENTRY_SIZE) as *mut u8;
+        if data.is_null() {
+            println!("Panic: failed in 'Stack::new::user_stack'");
+            cpu::halt();
+        }
+
+        kprintln!(
+            "Stack::new, memory block = [0x{:x}; 0x{:x}]",
+            start as usize,
+            (data as usize + consts::STACK_ENTRY_SIZE)
+        );
+
+        Box::new(Stack { data, size, is_kernel_stack: false })
+    }
 
     pub fn stack_end(&self) -> *mut u64 {
         self.data as *mut u64
     }
+
+    // Startadresse des Speicherblocks ('data' zeigt auf den letzten Eintrag)
+    fn stack_start(&self) -> *mut u8 {
+        ((self.data as usize) + consts::STACK_ENTRY_SIZE - self.size) as *mut u8
+    }
 }
 
 impl Drop for Stack {
     fn drop(&mut self) {
+        // Die Page-Frames eines User-Stacks gibt der Prozess frei, wenn
+        // der Thread entfernt wird (siehe 'Process::remove_thread')
+        if !self.is_kernel_stack || self.data.is_null() {
+            return;
+        }
         unsafe {
             let layout = Layout::from_size_align_unchecked(self.size, consts::STACK_ALIGNMENT);
-            allocator::dealloc(self.data, layout);
+            allocator::dealloc(self.stack_start(), layout);
         }
     }
 }
@@ -105,6 +115,7 @@ impl Default for Stack {
         Self {
             data: 0 as *mut u8,
             size: 0,
+            is_kernel_stack: true,
         }
     }
 }
\ No newline at end of file
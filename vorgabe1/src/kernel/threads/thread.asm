@@ -13,6 +13,7 @@
 ; EXPORTIERTE FUNKTIONEN
 [GLOBAL _thread_kernel_start]
 [GLOBAL _thread_user_start]
+[GLOBAL _thread_fork_start]
 [GLOBAL _thread_switch]
 [GLOBAL _thread_set_segment_register]
 
@@ -154,6 +155,24 @@ _thread_user_start:
     iretq                       ; Thread-Wechsel und Umschalten in den User-Mode!
 
 
+;
+; fn _thread_fork_start (old_rsp0: u64); 
+;
+; Wie '_thread_user_start', aber fuer das Kind von 'fork': laedt die vom
+; Elternteil uebernommenen Register und liefert 0 als Ergebnis von 'fork'
+; Wird nur 1x in 'switch_to_usermode' in 'thread.rs' gerufen
+_thread_fork_start:
+    mov rsp, rdi                ; 1. Parameter -> load 'old_rsp'
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    xor rax, rax                ; Rueckgabewert von 'fork' im Kind
+    iretq                       ; Thread-Wechsel und Umschalten in den User-Mode!
+
+
 ;
 ; fn _thread_set_segment_register(); 
 ;
@@ -7,6 +7,7 @@
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use core::fmt;
 
 // Füge diesen Import hinzu
@@ -15,8 +16,11 @@ use core::arch::asm;
 use crate::consts;
 use crate::devices::cga;
 use crate::kernel::cpu;
+use crate::kernel::threads::kmutex::KMutex;
+use crate::kernel::threads::process::Process;
 use crate::kernel::threads::scheduler;
 use crate::kernel::threads::stack;
+use crate::kernel::syscall::user_api::usr_exit;
 use crate::mylib::queue::Link;
 use crate::kernel::paging::frames::PhysAddr;
 use crate::kernel::paging::pages;
@@ -28,6 +32,7 @@ use crate::hello_world_thread::hello_world_thread_entry;
 extern "C" {
     fn _thread_kernel_start(old_rsp0: u64);
     fn _thread_user_start(old_rsp0: u64);
+    fn _thread_fork_start(old_rsp0: u64);
     
     //fn _thread_switch(now_rsp0: *mut u64, then_rsp0: u64, then_rsp0_end: u64);
     fn _thread_switch(now_rsp0: *mut u64, then_rsp0: u64, then_rsp0_end: u64, then_pml4: u64);
@@ -39,12 +44,67 @@ extern "C" {
     fn _tss_set_rsp0(old_rsp0: u64);
 }
 
+// Zustaende eines Threads
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ThreadState {
+    Ready,   // in der Ready-Queue
+    Running, // hat gerade die CPU
+    Blocked, // wartet in einer WaitQueue auf ein Ereignis
+    Zombie,  // beendet, Ressourcen noch nicht freigegeben
+}
+
+// Statische Prioritaeten (hoeherer Wert = wichtiger), siehe 'policy'
+pub const PRIO_LEVELS: usize = 8;
+pub const IDLE_PRIORITY: usize = 0; // nur fuer den Idle-Thread
+pub const DEFAULT_PRIORITY: usize = 4;
+
+// Zeitscheibe in Ticks (1 Tick = 10ms), nach der ein Thread verdraengt wird
+pub const DEFAULT_QUANTUM: usize = 5;
+
+// CPU-Verbrauch eines Threads, siehe 'Scheduler::get_stats'
+// (wird auch per Systemaufruf in den User-Mode kopiert)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadStats {
+    pub tid: u64,
+    pub quantum: u64,              // Zeitscheibe in Ticks
+    pub ticks_run: u64,            // insgesamt gerechnete Ticks
+    pub voluntary_switches: u64,   // CPU abgegeben (yield, block, sleep, exit)
+    pub involuntary_switches: u64, // vom PIT verdraengt
+}
+
+// Register eines User-Threads beim Systemaufruf 'fork', mit denen das Kind im
+// Ring 3 weiterlaeuft. Die uebrigen Register sichert der Syscall-Handler nicht
+// (sie duerfen laut ABI veraendert werden), 'rax' ist im Kind 0.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UserContext {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rflags: u64,
+    pub rbx: u64,
+    pub rbp: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+}
+
 // Verwaltungsstruktur fuer einen Thread
 #[repr(C)]
 pub struct Thread {
     tid: usize,
     is_kernel_thread: bool,
-    pml4_addr: PhysAddr, // Einstieg in die Seitentabellen
+    state: ThreadState,
+    exit_code: i64, // gueltig, sobald der Thread ein Zombie ist
+    priority: usize, // statische Prioritaet
+    level: usize,    // aktuelle Ebene im MLFQ (0 = hoechste)
+    quantum: usize,    // Zeitscheibe in Ticks
+    slice_used: usize, // Ticks seit der letzten Einlastung
+    ticks_run: u64,
+    voluntary_switches: u64,
+    involuntary_switches: u64,
+    process: Arc<KMutex<Process>>, // Prozess, dessen Adressraum der Thread nutzt
+    pml4_addr: PhysAddr, // Einstieg in die Seitentabellen (Kopie aus 'process')
     old_rsp0: u64, // letzter genutzter Stackeintrag im Kernel-Stack
     // der User-Stack-Ptr. wird auto. durch die Hardware gesichert
     //warum nicht old_rsp3?? => sie Folien "Stackaufbau bei einem Ringwechsel"
@@ -57,39 +117,73 @@ pub struct Thread {
 
     kernel_stack: Box<stack::Stack>, // Speicher fuer den Kernel-Stack
     entry: extern "C" fn(),
+    user_rip: u64, // Einstieg eines geladenen Programms (0 = 'kickoff_user_thread')
+    fork_context: Option<UserContext>, // Kind von 'fork', startet mit diesen Registern
 }
 
 impl Thread {
-    // Neuen Thread anlegen
+    // Neuen Thread in einem neuen Prozess (mit eigenem Adressraum) anlegen
     //     pub fn new(my_tid: usize, myentry: extern "C" fn(), kernel_thread: bool) -> Box<Thread> {
     pub fn new(myentry: extern "C" fn(), kernel_thread: bool) -> Box<Thread> {
+        Thread::new_in_process(&Process::new(), myentry, kernel_thread)
+    }
+
+    // Neuen Thread im Prozess 'process' anlegen, er teilt sich den Adressraum
+    // mit den anderen Threads des Prozesses
+    pub fn new_in_process(process: &Arc<KMutex<Process>>, myentry: extern "C" fn(), kernel_thread: bool) -> Box<Thread> {
 
         kprintln!("{}", if kernel_thread {"Ein neuer Kernel-Thread wird erstellt....."} else {"Ein neuer User-Thread wird erstellt....."});
 //----Aufgabe X Blatt 4: Pageframes ----------------------------------------------------------------------------------------------        
         let mytid = scheduler::next_thread_id();
 
-        // Page-Tables anlegen
-        let new_pml4_addr = pages::pg_init_kernel_tables();
+        // Page-Tables des Prozesses nutzen, Stack-Slot reservieren
+        let user_stack_start = process.lock().add_thread(mytid);
 //-----------------------------------------------------------------------------------------------------------------------------------------
+        Thread::create(process, mytid, user_stack_start, myentry, kernel_thread)
+    }
+
+    // Thread-Objekt fuer 'mytid' anlegen, der Thread ist bereits im Prozess
+    // eingetragen und hat dort seinen User-Stack ab 'user_stack_start'
+    fn create(
+        process: &Arc<KMutex<Process>>,
+        mytid: usize,
+        user_stack_start: usize,
+        myentry: extern "C" fn(),
+        kernel_thread: bool,
+    ) -> Box<Thread> {
+        let new_pml4_addr = process.lock().get_pml4_addr();
+
         // Speicher fuer die Stacks anlegen
         //let my_kernel_stack = stack::Stack::new(consts::STACK_SIZE);
-        let my_kernel_stack = stack::Stack::new(consts::STACK_SIZE, true, new_pml4_addr);
+        let my_kernel_stack = stack::Stack::new(consts::STACK_SIZE);
 
 //----Aufgabe 3 Blatt 1: User-Stack eingebaut----------------------------------------------------------------------------------------------
         
         //let my_user_stack = stack::Stack::new(consts::STACK_SIZE);
-        let my_user_stack = stack::Stack::new(consts::STACK_SIZE, false, new_pml4_addr);
+        let my_user_stack = stack::Stack::new_user(consts::USER_STACK_SIZE, user_stack_start);
 //-----------------------------------------------------------------------------------------------------------------------------------------
 
         // Thread-Objekt anlegen
         let mut threadobj = Box::new(Thread {
             tid: mytid,
             is_kernel_thread: kernel_thread,
+            state: ThreadState::Ready,
+            exit_code: 0,
+            priority: DEFAULT_PRIORITY,
+            level: 0,
+            quantum: DEFAULT_QUANTUM,
+            slice_used: 0,
+            ticks_run: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+            process: process.clone(),
             pml4_addr: new_pml4_addr,
             old_rsp0: 0,
             user_stack: my_user_stack,
             kernel_stack: my_kernel_stack,
             entry: myentry,
+            user_rip: 0,
+            fork_context: None,
         });
 
         threadobj.prepare_kernel_stack();
@@ -97,6 +191,50 @@ impl Thread {
         threadobj
     }
 
+    // Neuen User-Thread fuer ein geladenes Programm anlegen (siehe 'elf'). Der
+    // Thread springt im Ring 3 direkt an 'user_rip', der Code liegt im
+    // Adressraum von 'process' und nicht im Kernel.
+    pub fn new_user_program(process: &Arc<KMutex<Process>>, user_rip: u64) -> Box<Thread> {
+        let mut threadobj = Thread::new_in_process(process, user_program_entry, false);
+        threadobj.user_rip = user_rip;
+        threadobj
+    }
+
+    // Einzigen Thread des Kindes von 'fork' anlegen. 'child_tid' ist bereits in
+    // 'process' eingetragen (siehe 'Process::fork'), der Thread setzt im Ring 3
+    // mit 'context' fort und bekommt 0 als Ergebnis des Systemaufrufs.
+    pub fn new_forked(process: &Arc<KMutex<Process>>, child_tid: usize, context: UserContext) -> Box<Thread> {
+        let user_stack_start = process
+            .lock()
+            .get_stack_start(child_tid)
+            .expect("new_forked: Kind hat keinen User-Stack");
+        let mut threadobj = Thread::create(process, child_tid, user_stack_start, user_program_entry, false);
+        threadobj.user_rip = context.rip;
+        threadobj.fork_context = Some(context);
+        threadobj
+    }
+
+    // Register des laufenden Systemaufrufs im Ring 3 lesen (fuer 'fork').
+    // Die CPU legt den Interrupt-Stackframe an das Ende des Kernel-Stacks (auf
+    // 16 Byte abgerundet), darunter sichert '_syscall_handler' rbx, rbp, r12-r15.
+    pub fn get_syscall_context(&self) -> UserContext {
+        let frame_end = (self.kernel_stack.stack_end() as u64) & !0xf;
+        let frame = frame_end as *const u64;
+        unsafe {
+            UserContext {
+                rip: *frame.offset(-5),
+                rflags: *frame.offset(-3),
+                rsp: *frame.offset(-2),
+                rbx: *frame.offset(-6),
+                rbp: *frame.offset(-7),
+                r12: *frame.offset(-8),
+                r13: *frame.offset(-9),
+                r14: *frame.offset(-10),
+                r15: *frame.offset(-11),
+            }
+        }
+    }
+
     // Starten des 1. Kernel-Threads (rsp0 zeigt auf den praeparierten Stack)
     // Wird vom Scheduler gerufen, wenn dieser gestartet wird.
     // Alle anderen Threads werden mit 'switch' angestossen
@@ -226,9 +364,16 @@ impl Thread {
         //             < 8 Bytes | 64 Bit > => 16 hex zahlen
 
         //Adresse der nächsten auszuführenden Instruktion im User-Mode wird die kickoff_user_thread Funktion
-        let kickoff_user_addr = kickoff_user_thread as *const (); 
-        //Pointer um _thread_user_start später das benötigte Objekt zu übergeben
-        let object: *const Thread = self; 
+        //bzw. der Einstieg des geladenen Programms
+        let kickoff_user_addr = if self.user_rip != 0 {
+            self.user_rip as *const ()
+        } else {
+            kickoff_user_thread as *const ()
+        };
+        //Parameter fuer _thread_user_start: die Einstiegsfunktion fuer 'kickoff_user_thread'.
+        //Der Thread-Deskriptor liegt im Kernel und ist im Ring 3 nicht lesbar.
+        //(ein geladenes Programm bekommt keinen Parameter, rdi = 0)
+        let object: u64 = if self.user_rip != 0 { 0 } else { self.entry as u64 };
         // sp0 zeigt ans Ende des Speicherblocks des Kernel-Stacks
         let sp0: *mut u64 = self.kernel_stack.stack_end(); //0xdead im Speicher aus prepare_kernel_stack
         unsafe{
@@ -261,9 +406,24 @@ impl Thread {
             //*sp0.offset(-6) = 0x0000000000000000; //Error Code
         // xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx Interrupt Stack Ende xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx \\
             // _thread_user_start() erwartet noch ein Objekt, welches es von Stack nimmt 
-            *sp0.offset(-6) = object as u64;
+            *sp0.offset(-6) = object;
             //update old_rsp0 indem wir die 6 gepusht Adressen mit je 8 Byte abziehen
             //self.old_rsp0 = (sp0 as u64) - 6 * 8;
+
+            // Kind von 'fork': statt 'object' die Register aus dem Systemaufruf des
+            // Elternteils (in der Reihenfolge, in der '_thread_fork_start' sie holt)
+            if let Some(ctx) = self.fork_context {
+                *sp0.offset(-2) = ctx.rsp;
+                *sp0.offset(-3) = ctx.rflags;
+                *sp0.offset(-6) = ctx.rbx;
+                *sp0.offset(-7) = ctx.rbp;
+                *sp0.offset(-8) = ctx.r12;
+                *sp0.offset(-9) = ctx.r13;
+                *sp0.offset(-10) = ctx.r14;
+                *sp0.offset(-11) = ctx.r15;
+                _thread_fork_start((sp0 as u64) - 11 * 8);
+            }
+
             // In den Ring 3 schalten -> Aufruf von '_thread_user_start' in thread.asm und Aufruf von iretq
             _thread_user_start((sp0 as u64) - 6 * 8); 
         } 
@@ -290,6 +450,97 @@ impl Thread {
         unsafe { (*thread_object).tid }
     }
 
+    pub fn get_process(&self) -> &Arc<KMutex<Process>> {
+        &self.process
+    }
+
+    pub fn get_pid(&self) -> usize {
+        self.process.lock().get_pid()
+    }
+
+    pub fn get_state(&self) -> ThreadState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: ThreadState) {
+        self.state = state;
+    }
+
+    pub fn get_priority(&self) -> usize {
+        self.priority
+    }
+
+    // Prioritaet setzen, bevor der Thread in die Ready-Queue kommt
+    pub fn set_priority(&mut self, priority: usize) {
+        self.priority = priority.min(PRIO_LEVELS - 1);
+    }
+
+    pub fn get_level(&self) -> usize {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: usize) {
+        self.level = level;
+    }
+
+    pub fn get_quantum(&self) -> usize {
+        self.quantum
+    }
+
+    pub fn set_quantum(&mut self, quantum: usize) {
+        self.quantum = quantum.max(1);
+    }
+
+    // Ticks seit der letzten Einlastung
+    pub fn get_slice_used(&self) -> usize {
+        self.slice_used
+    }
+
+    // Zeitscheibe aufgebraucht?
+    pub fn quantum_expired(&self) -> bool {
+        self.slice_used >= self.quantum
+    }
+
+    pub fn get_stats(&self) -> ThreadStats {
+        ThreadStats {
+            tid: self.tid as u64,
+            quantum: self.quantum as u64,
+            ticks_run: self.ticks_run,
+            voluntary_switches: self.voluntary_switches,
+            involuntary_switches: self.involuntary_switches,
+        }
+    }
+
+    // Thread wird eingelastet, neue Zeitscheibe (nur durch den Scheduler)
+    pub(super) fn start_slice(&mut self) {
+        self.slice_used = 0;
+    }
+
+    // Einen Tick des PIT abrechnen (nur durch den Scheduler)
+    pub(super) fn account_tick(&mut self) {
+        self.ticks_run += 1;
+        self.slice_used += 1;
+    }
+
+    // Thread verliert die CPU (nur durch den Scheduler)
+    pub(super) fn count_switch(&mut self, voluntary: bool) {
+        if voluntary {
+            self.voluntary_switches += 1;
+        } else {
+            self.involuntary_switches += 1;
+        }
+    }
+
+    pub fn get_exit_code(&self) -> i64 {
+        self.exit_code
+    }
+
+    // Thread als beendet markieren, wird nur von 'Scheduler::exit' gerufen
+    pub fn set_zombie(&mut self, exit_code: i64) {
+        self.exit_code = exit_code;
+        self.state = ThreadState::Zombie;
+    }
+
     pub fn get_raw_pointer(&mut self) -> *mut Thread {
         self
     }
@@ -297,6 +548,17 @@ impl Thread {
 
 
 
+// Freigabe aller Ressourcen eines beendeten Threads. Der Kernel-Stack wird
+// durch 'Drop' von 'Stack' freigegeben, der User-Stack vom Prozess. War es
+// der letzte Thread, baut 'Drop' von 'Process' danach den Adressraum ab.
+// Wird vom Reaper im Scheduler ausgeloest, also nie auf dem Kernel-Stack
+// des Threads selbst.
+impl Drop for Thread {
+    fn drop(&mut self) {
+        self.process.lock().remove_thread(self.tid);
+    }
+}
+
 // Notwendig, für die Queue-Implementierung im Scheduler
 impl PartialEq for Thread {
     fn eq(&self, other: &Self) -> bool {
@@ -346,22 +608,31 @@ pub extern "C" fn kickoff_kernel_thread(object: *mut Thread) {
             ((*object).entry)();
         }
     }
-    loop {}
+
+    // Die Einstiegsfunktion ist zurueckgekehrt -> Thread beenden
+    scheduler::Scheduler::exit(0);
+}
+
+//
+// Platzhalter fuer 'entry' bei geladenen Programmen, der Thread startet
+// im Ring 3 an 'user_rip' und ruft diese Funktion nie auf
+//
+extern "C" fn user_program_entry() {
+    panic!("user_program_entry: Thread eines geladenen Programms ohne Einstieg");
 }
 
 //
 // Dies ist die  Rust-Funktion, die aufgerufen wird, wenn ein
-// Kernel-Thread (Ring 0) in den Ring 3 versetzt wird
+// Kernel-Thread (Ring 0) in den Ring 3 versetzt wird. Sie laeuft
+// bereits im Ring 3 und liegt daher in der Sektion '.user_text'.
 //
 #[no_mangle]
-pub extern "C" fn kickoff_user_thread(object: *mut Thread) {
+#[link_section = ".user_text"]
+pub extern "C" fn kickoff_user_thread(entry: extern "C" fn()) {
     // Einstiegsfunktion des Threads aufrufen
+    entry();
 
-    /*
-        Hier muss Code eingefuegt werden
-    */
-    unsafe {
-        ((*object).entry)();
-    }
-    loop {}
+    // Wir sind im Ring 3 und muessen den Kernel per Systemaufruf bitten,
+    // den Thread zu beenden
+    usr_exit(0);
 }
@@ -0,0 +1,47 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: wait_queue                                                      ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Descr.: Queue of blocked threads waiting for an event. Threads are      ║
+   ║         parked with 'Scheduler::block' and released again with          ║
+   ║         'Scheduler::wake_one' or 'Scheduler::wake_all'.                 ║
+   ║                                                                         ║
+   ║         The queue is only accessed with interrupts disabled, so it can  ║
+   ║         be used from ISRs, e.g. the keyboard or the PIT.                ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+use alloc::boxed::Box;
+use spin::Mutex;
+
+use crate::kernel::threads::thread;
+use crate::mylib::queue;
+
+pub struct WaitQueue {
+    threads: Mutex<queue::Queue<Box<thread::Thread>>>,
+}
+
+// Notwendig, damit eine WaitQueue als 'static' angelegt werden kann
+unsafe impl Send for WaitQueue {}
+unsafe impl Sync for WaitQueue {}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            threads: Mutex::new(queue::Queue::new()),
+        }
+    }
+
+    // Blockierten Thread einreihen (nur durch 'Scheduler::block')
+    pub(super) fn enqueue(&self, that: Box<thread::Thread>) {
+        self.threads.lock().enqueue(that);
+    }
+
+    // Ersten wartenden Thread aushaengen (nur durch 'Scheduler::wake_*')
+    pub(super) fn dequeue(&self) -> Option<Box<thread::Thread>> {
+        self.threads.lock().dequeue()
+    }
+
+    // Wartet mindestens ein Thread?
+    pub fn has_waiters(&self) -> bool {
+        !self.threads.lock().is_empty()
+    }
+}
@@ -61,6 +61,23 @@ impl<T: PartialEq> Queue<T> {
         })
     }
 
+    // Ist die Liste leer?
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    // Pruefen, ob ein Element die Bedingung 'f' erfuellt
+    pub fn contains<F: Fn(&T) -> bool>(&self, f: F) -> bool {
+        let mut node = self.head.clone();
+        while let Some(n) = node {
+            if f(&n.borrow().data) {
+                return true;
+            }
+            node = n.borrow().next.clone();
+        }
+        false
+    }
+
     // Suche und entferne das Element 'data'
     // Rueckgabewert: true:  falls das Element gefunden und geloescht wurde
     //                false: sonst
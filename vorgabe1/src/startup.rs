@@ -37,7 +37,7 @@ use consts::KERNEL_HEAP_SIZE;
 use consts::PAGE_FRAME_SIZE;
 use consts::TEMP_HEAP_SIZE;
 use kernel::paging::frames;
-use kernel::paging::frames::PhysAddr;
+use kernel::paging::frames::{FrameOwner, PhysAddr};
 use kernel::paging::pages;
 use core::panic::PanicInfo;
 
@@ -49,10 +49,13 @@ use devices::pit; // timer
 
 use kernel::allocator;
 use kernel::cpu;
+use kernel::elf;
 use kernel::interrupts;
 use kernel::syscall::syscall_dispatcher;
 use kernel::threads::idle_thread;
+use kernel::threads::policy::PolicyKind;
 use kernel::threads::scheduler;
+use kernel::threads::thread;
 use kernel::threads::thread::Thread;
 
 use user::hello_world_thread;
@@ -107,8 +110,15 @@ pub extern "C" fn kmain(mbi: u64) {
     let kernel_region = get_kernel_image_region();
     kprintln!("kmain, kernel_image: {:?}", kernel_region);
 
+    // GRUB legt die Module direkt hinter das Kernel-Image, der temporaere Heap
+    // muss dahinter liegen (auf das naechste MB aufrunden)
+    let mut image_end = kernel_region.end;
+    if let Some(modules_end) = multiboot::get_modules_end(mbi) {
+        image_end = image_end.max(modules_end | 0xFFFFF);
+    }
+
     // Verfuegbaren physikalischen Speicher ermitteln (exklusive Kernel-Image und Heap)
-    let heap_region = create_temp_heap(kernel_region.end as usize);
+    let heap_region = create_temp_heap(image_end as usize);
     kprintln!("kmain, heap: {:?}", heap_region);
 
     // Verfuegbaren physikalischen Speicher ermitteln (exklusive Kernel-Image und Heap)
@@ -119,6 +129,16 @@ pub extern "C" fn kmain(mbi: u64) {
     // mbi == multiboot address 
     multiboot::dump(mbi);
 
+    // Scheduling-Strategie von der Kommandozeile lesen (z.B. "sched=mlfq"),
+    // solange die Multiboot-Infos noch nicht ueberschrieben sein koennen
+    let sched_policy = match multiboot::get_cmdline_arg(mbi, "sched") {
+        Some(name) => PolicyKind::from_name(name).unwrap_or_else(|| {
+            kprintln!("kmain: unknown scheduling policy '{}', using round robin", name);
+            PolicyKind::RoundRobin
+        }),
+        None => PolicyKind::RoundRobin,
+    };
+
     // Page-Frame-Management einrichten
     frames::pf_init(&mut phys_mem);
 
@@ -126,11 +146,14 @@ pub extern "C" fn kmain(mbi: u64) {
     let pml4_addr = pages::pg_init_kernel_tables();
     kprintln!("kmain: setze CR3 auf 0x{:x}", pml4_addr.raw());
     pages::pg_set_cr3(pml4_addr);
+    pages::pg_enable_global_pages();
+    pages::pg_enable_write_protect();
+    pages::pg_enable_smep_smap();
 
     // Kernel Heap einrichten
     kprintln!("kmain: Kernel Heap einrichten");
-    let kernel_heap = frames::pf_alloc(KERNEL_HEAP_SIZE.div_ceil(PAGE_FRAME_SIZE), true); // Teilen und aufrunden um 4kb alignment sicherzustellen
-    allocator::init(kernel_heap.to_start_address(), TEMP_HEAP_SIZE);
+    let kernel_heap = frames::pf_alloc_uninit(KERNEL_HEAP_SIZE.div_ceil(PAGE_FRAME_SIZE), true, FrameOwner::KernelHeap); // Teilen und aufrunden um 4kb alignment sicherzustellen
+    allocator::init(kernel_heap.to_start_address(), KERNEL_HEAP_SIZE);
 
     kprintln!(".... dumping ....");
     frames::pf_dump_lists();
@@ -165,11 +188,15 @@ pub extern "C" fn kmain(mbi: u64) {
     ); --------- old ---------
      */
 
+    // Scheduling-Strategie einrichten
+    scheduler::Scheduler::set_policy(sched_policy.create());
+
     // Idle-Thread eintragen
-    let idle_thread = Thread::new(
+    let mut idle_thread = Thread::new(
         idle_thread::idle_thread_entry,
         true, //hier setzen welcher Ring Thread Idle läuft Aufgabe 1
     );
+    idle_thread.set_priority(thread::IDLE_PRIORITY);
     scheduler::Scheduler::ready(idle_thread);
 
     // für blatt 4 erstmal userthread ausschalten
@@ -181,6 +208,17 @@ pub extern "C" fn kmain(mbi: u64) {
     );
     scheduler::Scheduler::ready(hello_world_thread);/**/
 
+    // User-Programme aus den Multiboot-Modulen laden (siehe 'grub.cfg')
+    for module in multiboot::get_modules(mbi) {
+        match elf::load_program(module.data()) {
+            Ok(thread) => {
+                kprintln!("kmain: Programm '{}' geladen, tid={}", module.name, Thread::get_tid(&*thread));
+                scheduler::Scheduler::ready(thread);
+            }
+            Err(e) => kprintln!("kmain: Programm '{}' nicht geladen: {:?}", module.name, e),
+        }
+    }
+
     // Scheduler starten & Interrupts erlauben
     scheduler::Scheduler::schedule();
 }
@@ -1,7 +1,17 @@
 use crate::kernel::cpu;
-use crate::kernel::syscall::user_api::{usr_getlastkey, usr_gettid, usr_hello_world, usr_read, usr_write};
+use crate::kernel::syscall::user_api::{usr_getlastkey, usr_gettid, usr_hello_world, usr_read, usr_sleep, usr_write};
 use crate::kernel::threads::scheduler;
 
+// Ausgabe des Threads, muss im Ring 3 lesbar sein
+const HELLO_LEN: usize = 1;
+#[link_section = ".user_rodata"]
+static HELLO_OUTPUT: [u8; HELLO_LEN] = *b"U";
+
+// Laeuft im Ring 3 und darf daher nur Code und Daten aus '.user_text' nutzen
+// (z.B. die Syscall-Wrapper), nicht aber 'print!' oder den restlichen Kernel.
+// Auch Methoden aus 'core' (z.B. 'as_ptr', 'len') werden im Debug-Build nicht
+// eingebettet und liegen im Kernel.
+#[link_section = ".user_text"]
 pub extern "C" fn hello_world_thread_entry() {
     //  let tid = scheduler::get_active_tid();
     //  println!("Hello World! thread-id = {}", tid);
@@ -12,18 +22,13 @@ pub extern "C" fn hello_world_thread_entry() {
     //test_syscalls(0);
 
     loop {
-        print!("U");
-
-        let mut x: u64 = 0;
-        loop {
-            x = x + 1;
-            if x > 100000000 {
-                break;
-            }
-        }
+        usr_write(core::ptr::addr_of!(HELLO_OUTPUT) as *const u8, HELLO_LEN as u64);
+        usr_sleep(500);
     }
 }
 
+// Achtung: liegt nicht in '.user_text' und loest daher im Ring 3 einen
+// Page-Fault aus, nur zum Testen der Syscalls aus einem Kernel-Thread
 fn test_syscalls(call_id: u8) {
     match call_id {
         0 => usr_hello_world(), // teste Funktionsweise sys_hello_word aus Ring 3 heraus
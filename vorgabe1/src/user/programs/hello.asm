@@ -0,0 +1,49 @@
+;******************************************************************************
+;*                                                                            *
+;*                  h e l l o . a s m                                         *
+;*                                                                            *
+;*----------------------------------------------------------------------------*
+;* Beschreibung:    Minimales User-Programm, wird als eigenes ELF64-Programm  *
+;*                  gelinkt und von GRUB als Multiboot-Modul geladen (siehe   *
+;*                  'grub.cfg'). Laeuft im Ring 3 und nutzt nur Syscalls.     *
+;*                                                                            *
+;*                  Syscall-Nummern siehe 'kernel/syscall/user_api.rs'.       *
+;******************************************************************************
+
+[GLOBAL _start]
+
+SYSNO_WRITE: equ 1
+SYSNO_EXIT:  equ 5
+SYSNO_SLEEP: equ 7
+
+[SECTION .text]
+[BITS 64]
+
+_start:
+	mov rbx, 5                ; Anzahl Ausgaben
+
+.loop:
+	mov rax, SYSNO_WRITE
+	mov rdi, msg
+	mov rsi, msg_len
+	int 0x80
+
+	mov rax, SYSNO_SLEEP
+	mov rdi, 1000             ; ms
+	int 0x80
+
+	dec rbx
+	jnz .loop
+
+	mov rax, SYSNO_EXIT
+	mov rdi, 0                ; Exit-Code
+	int 0x80
+
+.hang:
+	jmp .hang                 ; exit kehrt nicht zurueck
+
+
+[SECTION .data]
+
+msg:     db "Hello from an ELF user program!", 10
+msg_len: equ $ - msg